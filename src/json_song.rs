@@ -0,0 +1,103 @@
+// Songs shared as note lists instead of MIDI files, in the format
+// [{"time_ms": 0, "note": 60, "track": 0, "duration": 500}, ...]
+use crate::webfishing_player::EventSource;
+use midly::{
+    num::{u15, u24, u28, u4, u7},
+    Format, Header, MetaMessage, MidiMessage, Timing, Track, TrackEvent, TrackEventKind,
+};
+use serde::{de::Error as _, Deserialize};
+use std::collections::BTreeMap;
+
+// One tick is one millisecond at this resolution and tempo
+const TICKS_PER_BEAT: u16 = 1000;
+const MICROS_PER_BEAT: u32 = 1_000_000;
+const DEFAULT_VELOCITY: u8 = 100;
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+struct JsonNote {
+    time_ms: u64,
+    note: u8,
+    track: usize,
+    duration: u64,
+    #[serde(default = "default_velocity")]
+    velocity: u8,
+}
+
+fn default_velocity() -> u8 {
+    DEFAULT_VELOCITY
+}
+
+/// A song built from a JSON note list, with one track per track number used in the list
+#[derive(Debug, Clone)]
+pub struct JsonSong {
+    tracks: Vec<Track<'static>>,
+}
+
+impl JsonSong {
+    pub fn parse(data: &[u8]) -> Result<Self, serde_json::Error> {
+        let notes: Vec<JsonNote> = serde_json::from_slice(data)?;
+
+        // Absolute time of every note event by track number. The numbers can have gaps,
+        // the tracks of the song are the numbers that are used in order
+        let mut timelines: BTreeMap<usize, Vec<(u64, MidiMessage)>> = BTreeMap::new();
+        for note in &notes {
+            let key = u7::try_from(note.note)
+                .ok_or_else(|| serde_json::Error::custom(format!("note {} is above 127", note.note)))?;
+            let vel = u7::try_from(note.velocity)
+                .ok_or_else(|| serde_json::Error::custom(format!("velocity {} is above 127", note.velocity)))?;
+            let end = note.time_ms.checked_add(note.duration).ok_or_else(|| {
+                serde_json::Error::custom(format!("note at {}ms lasts too long", note.time_ms))
+            })?;
+            let timeline = timelines.entry(note.track).or_default();
+            timeline.push((note.time_ms, MidiMessage::NoteOn { key, vel }));
+            timeline.push((end, MidiMessage::NoteOff { key, vel: u7::new(0) }));
+        }
+        // A song without notes still has a track for the tempo
+        if timelines.is_empty() {
+            timelines.insert(0, Vec::new());
+        }
+
+        let mut tracks = Vec::with_capacity(timelines.len());
+        for (index, mut timeline) in timelines.into_values().enumerate() {
+            // Keep each track on its own channel, skipping the percussion channel
+            let channel = u4::new([0, 1, 2, 3, 4, 5, 6, 7, 8, 10, 11, 12, 13, 14, 15][index % 15]);
+            // Release notes before new ones start on the same tick so repeated notes aren't cut short
+            timeline.sort_by_key(|(time, message)| (*time, matches!(message, MidiMessage::NoteOn { .. })));
+
+            let mut track = Vec::with_capacity(timeline.len() + 2);
+            // The tempo goes in the first track
+            if index == 0 {
+                track.push(TrackEvent {
+                    delta: u28::new(0),
+                    kind: TrackEventKind::Meta(MetaMessage::Tempo(u24::new(MICROS_PER_BEAT))),
+                });
+            }
+            let mut last_time = 0;
+            for (time, message) in timeline {
+                let delta = u32::try_from(time - last_time)
+                    .ok()
+                    .and_then(u28::try_from)
+                    .ok_or_else(|| serde_json::Error::custom(format!("gap before {}ms is too long", time)))?;
+                track.push(TrackEvent { delta, kind: TrackEventKind::Midi { channel, message } });
+                last_time = time;
+            }
+            track.push(TrackEvent {
+                delta: u28::new(0),
+                kind: TrackEventKind::Meta(MetaMessage::EndOfTrack),
+            });
+            tracks.push(track);
+        }
+
+        Ok(JsonSong { tracks })
+    }
+}
+
+impl<'a> EventSource<'a> for JsonSong {
+    fn header(&self) -> Header {
+        Header::new(Format::Parallel, Timing::Metrical(u15::new(TICKS_PER_BEAT)))
+    }
+
+    fn tracks(&self) -> &[Track<'a>] {
+        &self.tracks
+    }
+}
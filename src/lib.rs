@@ -0,0 +1,8 @@
+pub mod instruments;
+pub mod json_song;
+pub mod midi_file;
+pub mod midi_stream;
+// Only used in place of enigo, silent input sends to the game window through XWayland
+#[cfg(all(feature = "wayland", target_os = "linux", not(feature = "silent_input")))]
+mod uinput;
+pub mod webfishing_player;
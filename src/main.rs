@@ -1,22 +1,20 @@
-mod instruments;
-mod webfishing_player;
-use core::str;
-use dialoguer::{theme::ColorfulTheme, Confirm, FuzzySelect, Input, MultiSelect};
+use device_query::Keycode;
+use dialoguer::{theme::ColorfulTheme, Confirm, FuzzySelect, Input, MultiSelect, Select};
 use indicatif::MultiProgress;
 use indicatif_log_bridge::LogWrapper;
-use instruments::INSTRUMENTS;
-use log::{debug, error, info};
-use midly::{MetaMessage, MidiMessage, Smf, TrackEventKind};
+use log::{debug, error, info, warn};
+use midly::Format;
 use rusqlite::{params, Connection};
 use simple_logger::SimpleLogger;
-use std::{fs, io::stdin, path::Path, path::PathBuf, process::exit};
+use std::{collections::HashMap, fs, io::stdin, path::Path, path::PathBuf, process::exit, time::Duration};
 use tabled::{builder::Builder, settings::Style};
-use webfishing_player::{PlayerSettings, WebfishingPlayer};
-use xcap::Window;
+use webfishing_midi::webfishing_player::{
+    Calibration, ContinueOrAbort, EventSource, FretButton, FretInputMode, Hotkeys, HumanizeSettings, KeyMap, PlaybackState, PlayerSettings, RangeStrategy, RateLimitStrategy, ShiftWeight, SingMode, SongInfo, VoicePriority, WebfishingPlayer, WindowInfo, STANDARD_TUNING,
+    note_name, DEFAULT_PITCH_BEND_RANGE, MAX_FRET, PROGRESS_TEMPLATE, WINDOW_NAMES,
+};
 use chrono::{Local, NaiveTime, Timelike};
 
 const MIDI_DIR: &str = "./midi";
-const WINDOW_NAMES: [&str; 3] = ["steam_app_3146520", "Fish! (On the WEB!)", "Godot_Engine"];
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     let logger = SimpleLogger::new()
@@ -38,23 +36,31 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             tracks TEXT);",
         (),
     )?;
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS paused_sessions (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            path TEXT NOT NULL UNIQUE,
+            tick INTEGER NOT NULL,
+            loops INTEGER NOT NULL,
+            elapsed INTEGER NOT NULL);",
+        (),
+    )?;
 
-    let window = WINDOW_NAMES
-        .iter()
-        .find_map(|name| get_window(name))
-        .ok_or_else(|| {
-            error!("Could not find game window");
-            pause_and_exit(-1);
-        })
-        .unwrap();
+    let window = match WebfishingPlayer::find_window(&WINDOW_NAMES) {
+        Ok(window) => WindowInfo::from(&window),
+        Err(e) => {
+            error!("{}", e);
+            get_window_selection(&theme)?.unwrap_or_else(|| pause_and_exit(-1))
+        }
+    };
 
     info!(
         "Found window: {} {},{} {}x{}",
-        window.title(),
-        window.x(),
-        window.y(),
-        window.width(),
-        window.height()
+        window.title,
+        window.x,
+        window.y,
+        window.width,
+        window.height
     );
 
     let min_framerate: u64 = Input::with_theme(&theme)
@@ -63,11 +69,12 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         .interact_text()?;
 
     // Calculate the ideal delay in milliseconds
-    let input_sleep_duration: u64 = (1000 / min_framerate) as u64;
+    let input_sleep_duration: u64 = 1000 / min_framerate;
 
     loop {
-        let mut song_queue: Vec<PlayerSettings> = Vec::new();
+        let mut song_queue: Vec<(PathBuf, PlayerSettings)> = Vec::new();
         let mut default_selection = 0;
+        let mut calibrate = false;
 
         // Selection loop for adding songs to the queue
         loop {
@@ -84,47 +91,203 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                 }
             };
 
-            let (should_sing, loop_midi, add_another_song, playback_speed, start_time) = get_user_options(&theme)?;
+            let mut options = get_user_options(&theme)?;
+
+            let mut sing_mode = SingMode::Off;
+            // The melody track is picked once the tracks are selected
+            let mut sing_melody = false;
+            let mut hold_sing = false;
+            if options.should_sing {
+                let modes = [
+                    "Notes above a pitch",
+                    "Notes of the melody track",
+                    "The highest note at a time",
+                ];
+                let selection = Select::with_theme(&theme)
+                    .with_prompt("Select which notes to sing along with")
+                    .items(&modes)
+                    .default(0)
+                    .interact()?;
+                match selection {
+                    0 => {
+                        // Ask above what note to sing along
+                        let (tuning, max_fret) =
+                            WebfishingPlayer::with_capo(&options.tuning, options.max_fret, options.capo);
+                        let (min_note, max_note) = WebfishingPlayer::note_range(&tuning, max_fret);
+                        let sing_above = Input::with_theme(&theme)
+                            .with_prompt(format!(
+                                "Minimum pitch to sing note ({} {}-{} {}) Default:",
+                                min_note,
+                                note_name(min_note),
+                                max_note,
+                                note_name(max_note)
+                            ))
+                            .default(60)
+                            .interact_text()?;
+                        sing_mode = SingMode::AboveThreshold(sing_above);
+                    }
+                    1 => sing_melody = true,
+                    _ => sing_mode = SingMode::HighestNote,
+                }
 
-            let mut sing_above: u8 = 60;
-            if should_sing {
-                // Ask above what note to sing along
-                sing_above = Input::with_theme(&theme)
-                    .with_prompt("Minimum pitch to sing note (40-79) Default:")
-                    .default(60)
-                    .interact_text()?;
+                hold_sing = Confirm::with_theme(&theme)
+                    .with_prompt("Hold the sing key for as long as each note?")
+                    .default(false)
+                    .interact()?;
             }
 
-            // Add the selected song to the queue
-            let mut settings = match PlayerSettings::new(midi_data, loop_midi, should_sing, sing_above, playback_speed, start_time) {
+            // Add the selected song to the queue, note lists are read as JSON and everything else as MIDI
+            let mut builder = PlayerSettings::builder()
+                .loop_midi(options.loop_midi)
+                .sing_mode(sing_mode)
+                .playback_speed(options.playback_speed);
+            if let Some(start_time) = options.start_time {
+                builder = builder.start_time(start_time);
+            }
+            let parsed = if is_json_song(&midi_file_path) {
+                builder.build_json(&midi_data).map_err(|e| e.to_string())
+            } else {
+                builder.build(midi_data).map_err(|e| e.to_string())
+            };
+            let mut settings = match parsed {
                 Ok(settings) => settings,
                 Err(e) => {
-                    error!("Failed to parse MIDI data: {}", e);
+                    error!("Failed to parse song data: {}", e);
                     continue;
                 }
             };
 
-            let chosen_tracks =
-                get_tracks_selection(&midi_file_path, &settings.smf, &theme, &conn)?;
+            let chosen_tracks = if options.auto_select_tracks {
+                let tracks =
+                    WebfishingPlayer::auto_select_tracks(settings.source.as_ref(), options.skip_percussion);
+                info!("Automatically chose tracks {:?}", tracks);
+                tracks
+            } else {
+                get_tracks_selection(&midi_file_path, settings.source.as_ref(), &theme, &conn)?
+            };
+            if options.per_track_shift {
+                settings.track_shifts = get_track_shifts(&chosen_tracks, &theme)?;
+            }
+            if matches!(options.shift_weight, ShiftWeight::FavorTrack(_)) && !chosen_tracks.is_empty() {
+                let track = Select::with_theme(&theme)
+                    .with_prompt("Select the track to keep in range when transposing")
+                    .items(&chosen_tracks)
+                    .default(0)
+                    .interact()?;
+                options.shift_weight = ShiftWeight::FavorTrack(chosen_tracks[track]);
+            }
+            if sing_melody && !chosen_tracks.is_empty() {
+                let melody = Select::with_theme(&theme)
+                    .with_prompt("Select the melody track to sing along with")
+                    .items(&chosen_tracks)
+                    .default(0)
+                    .interact()?;
+                settings.sing_mode = SingMode::MelodyTrack(chosen_tracks[melody]);
+            } else if options.should_sing && chosen_tracks.len() > 1 {
+                let sing_tracks = MultiSelect::with_theme(&theme)
+                    .with_prompt("Select the tracks that can sing (none for all of them)")
+                    .items(&chosen_tracks)
+                    .interact()?;
+                if !sing_tracks.is_empty() {
+                    settings.sing_tracks = Some(sing_tracks.iter().map(|&i| chosen_tracks[i]).collect());
+                }
+            }
+            settings.hold_sing = hold_sing;
+            settings.contour_singing = options.contour_singing;
+            settings.start_bar = options.start_bar;
             settings.tracks = Some(chosen_tracks);
+            settings.speed_ramp = options.speed_ramp;
+            settings.tuning = options.tuning;
+            settings.chord_mode = options.chord_mode;
+            settings.dry_run = options.dry_run;
+            settings.key_map = options.key_map;
+            settings.hotkeys = options.hotkeys;
+            settings.progress_template = options.progress_template;
+            settings.voice_priority = options.voice_priority;
+            settings.range_strategy = options.range_strategy;
+            settings.allow_octave_substitution = options.allow_octave_substitution;
+            settings.shift_weight = options.shift_weight;
+            settings.manual_shift = options.manual_shift;
+            settings.interpret_cc = options.interpret_cc;
+            settings.interpret_pitch_bend = options.interpret_pitch_bend;
+            settings.pitch_bend_range = options.pitch_bend_range;
+            settings.vibrato_from_modwheel = options.vibrato_from_modwheel;
+            settings.count_in_beats = options.count_in_beats;
+            settings.movement_weight = options.movement_weight;
+            settings.prefer_open_strings = options.prefer_open_strings;
+            settings.optimize_fingering = options.optimize_fingering;
+            settings.min_velocity = options.min_velocity;
+            settings.quantize = options.quantize;
+            settings.sustain_restrum_ms = options.sustain_restrum_ms;
+            settings.min_restrum_ms = options.min_restrum_ms;
+            settings.skip_percussion = options.skip_percussion;
+            settings.dedup_tracks = options.dedup_tracks;
+            settings.auto_select_tracks = options.auto_select_tracks;
+            settings.max_fret = options.max_fret;
+            settings.capo = options.capo;
+            settings.max_fret_jump = options.max_fret_jump;
+            settings.disabled_strings = options.disabled_strings;
+            settings.string_preference = options.string_preference;
+            settings.auto_pause_on_blur = options.auto_pause_on_blur;
+            settings.on_input_error = options.on_input_error;
+            settings.report_timing = options.report_timing;
+            settings.max_notes_per_second = options.max_notes_per_second;
+            settings.rate_limit_strategy = options.rate_limit_strategy;
+            settings.loop_start = options.loop_section.map(|(start, _)| start);
+            settings.loop_end = options.loop_section.map(|(_, end)| end);
+            settings.press_hold_ms = options.press_hold_ms;
+            settings.inter_note_gap_ms = options.inter_note_gap_ms;
+            settings.humanize = options.humanize;
+            settings.scale_sleep_with_speed = options.scale_sleep_with_speed;
+            settings.fret_to_strum_delay_ms = options.fret_to_strum_delay_ms;
+            settings.double_strum = options.double_strum;
+            settings.calibration = options.calibration;
+            settings.fret_input_mode = options.fret_input_mode;
+            settings.fret_button = options.fret_button;
+            calibrate |= options.calibrate;
+
+            // Pick up where the song was quit last time
+            let song_path = midi_file_path.to_string_lossy();
+            match get_paused_session(&song_path, &conn) {
+                Ok(Some(state)) => {
+                    let whole_secs = state.elapsed_micros / 1_000_000;
+                    let resume = Confirm::with_theme(&theme)
+                        .with_prompt(format!(
+                            "Resume from {:02}:{:02} where the song was stopped?",
+                            whole_secs / 60,
+                            whole_secs % 60
+                        ))
+                        .default(true)
+                        .interact()?;
+                    if resume {
+                        settings.resume_from = Some(state);
+                    }
+                }
+                Ok(None) => {}
+                Err(err) => error!("Failed to get the paused session from database: {}", err),
+            }
 
-            song_queue.push(settings);
+            song_queue.push((midi_file_path, settings));
 
-            if loop_midi || !add_another_song {
+            if options.loop_midi || !options.add_another_song {
                 break; // Exit the selection loop
             }
         }
 
         // Play all songs in the queue
-        for (index, settings) in song_queue.into_iter().enumerate() {
-            let is_first_song = index == 0 && !settings.start_time.is_some();
+        let mut calibration = None;
+        for (index, (song_path, mut settings)) in song_queue.into_iter().enumerate() {
+            let is_first_song = index == 0 && settings.start_time.is_none();
+            if let Some(calibration) = calibration {
+                settings.calibration = calibration;
+            }
 
             let mut player = match WebfishingPlayer::new(
                 settings,
                 is_first_song,
                 input_sleep_duration,
                 &window,
-                &multi,
+                Some(&multi),
             ) {
                 Ok(player) => player,
                 Err(e) => {
@@ -133,7 +296,54 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                 }
             };
 
-            player.play();
+            // Calibrate once and use it for the rest of the queue
+            if calibrate && calibration.is_none() {
+                match player.calibrate() {
+                    Ok(new_calibration) => {
+                        println!(
+                            "Calibration (enter this in \"Set fretboard calibration\" to reuse it): {},{},{},{}",
+                            new_calibration.left,
+                            new_calibration.top,
+                            new_calibration.string_spacing,
+                            new_calibration.fret_spacing
+                        );
+                        calibration = Some(new_calibration);
+                    }
+                    Err(e) => error!("Error during calibration: {}", e),
+                }
+            }
+
+            let report = player.playability_report();
+            if report.clamped_notes > 0 {
+                let octaves: Vec<String> = report
+                    .unplayable_octaves
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, &count)| count > 0)
+                    .map(|(octave, count)| format!("octave {}: {}", octave as i32 - 1, count))
+                    .collect();
+                warn!(
+                    "{} of {} notes are out of the guitar range and will be clamped ({})",
+                    report.clamped_notes,
+                    report.total_notes,
+                    octaves.join(", ")
+                );
+            }
+
+            if let Err(e) = player.play() {
+                error!("Error during playback: {}", e);
+                continue;
+            }
+
+            // Remember where the song was quit, or forget it once it's played to the end
+            let song_path = song_path.to_string_lossy();
+            let saved = match player.interrupted_at() {
+                Some(state) => insert_paused_session(&song_path, state, &conn),
+                None => delete_paused_session(&song_path, &conn),
+            };
+            if let Err(err) = saved {
+                error!("Failed to save the paused session to database: {}", err);
+            }
         }
 
         // Ask if the user wants to play another song
@@ -149,13 +359,115 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     Ok(())
 }
 
-fn get_user_options(theme: &ColorfulTheme) -> Result<(bool, bool, bool, f64, Option<u64>), dialoguer::Error> {
+struct UserOptions {
+    should_sing: bool,
+    loop_midi: bool,
+    add_another_song: bool,
+    playback_speed: f64,
+    speed_ramp: Option<Duration>,
+    start_time: Option<u64>,
+    start_bar: Option<u32>,
+    tuning: Vec<u8>,
+    chord_mode: bool,
+    dry_run: bool,
+    key_map: KeyMap,
+    hotkeys: Hotkeys,
+    progress_template: Option<String>,
+    voice_priority: VoicePriority,
+    range_strategy: RangeStrategy,
+    allow_octave_substitution: bool,
+    shift_weight: ShiftWeight,
+    manual_shift: Option<i8>,
+    per_track_shift: bool,
+    interpret_cc: bool,
+    interpret_pitch_bend: bool,
+    pitch_bend_range: f64,
+    vibrato_from_modwheel: bool,
+    count_in_beats: u8,
+    movement_weight: f64,
+    prefer_open_strings: bool,
+    optimize_fingering: bool,
+    min_velocity: u8,
+    quantize: Option<u32>,
+    sustain_restrum_ms: Option<u64>,
+    min_restrum_ms: Option<u64>,
+    skip_percussion: bool,
+    dedup_tracks: bool,
+    auto_select_tracks: bool,
+    max_fret: i32,
+    capo: i32,
+    max_fret_jump: Option<i32>,
+    disabled_strings: Vec<bool>,
+    string_preference: Vec<i32>,
+    auto_pause_on_blur: bool,
+    on_input_error: ContinueOrAbort,
+    report_timing: bool,
+    max_notes_per_second: Option<u32>,
+    rate_limit_strategy: RateLimitStrategy,
+    loop_section: Option<(u64, u64)>,
+    press_hold_ms: Option<u64>,
+    inter_note_gap_ms: Option<u64>,
+    scale_sleep_with_speed: bool,
+    fret_to_strum_delay_ms: u64,
+    double_strum: bool,
+    calibration: Calibration,
+    fret_input_mode: FretInputMode,
+    fret_button: FretButton,
+    humanize: Option<HumanizeSettings>,
+    contour_singing: bool,
+    calibrate: bool,
+}
+
+fn get_user_options(theme: &ColorfulTheme) -> Result<UserOptions, dialoguer::Error> {
     let options = vec![
         "Sing along",
         "Loop the song",
         "Queue another song",
         "Set playback speed",
         "Set start time",
+        "Set guitar tuning",
+        "Strum chords together",
+        "Dry run (log inputs without sending them)",
+        "Set custom keybindings",
+        "Set which notes to keep in dense chords",
+        "Set transpose manually",
+        "Set transpose per track",
+        "Follow the sustain pedal",
+        "Count in before playing",
+        "Set fret movement weight",
+        "Loop a section of the song",
+        "Set key hold time and gap between inputs",
+        "Set fretboard calibration",
+        "Calibrate the fretboard with the mouse",
+        "Skip quiet notes",
+        "Quantize note timing",
+        "Re-strum held notes",
+        "Play the percussion channel",
+        "Limit the highest fret",
+        "Pause when the game loses focus",
+        "Set custom hotkeys",
+        "Set which notes the transpose keeps in range",
+        "Skip quick repeats of the same note",
+        "Set the progress bar layout",
+        "Set how notes out of range are played",
+        "Play notes an octave away when their strings are taken",
+        "Start from a bar",
+        "Report how far behind schedule inputs fall",
+        "Prefer open strings",
+        "Plan the fingering of the whole song",
+        "Change how frets are clicked",
+        "Add vibrato to sung notes from the modulation wheel",
+        "Keep playing when an input fails",
+        "Leave some strings unused",
+        "Leave out tracks that duplicate another",
+        "Follow pitch bends",
+        "Humanize timing",
+        "Sing with up and down keys that follow the melody",
+        "Click the frets in between on far jumps",
+        "Pick the guitar and melody tracks automatically",
+        "Limit the notes per second",
+        "Use a capo",
+        "Set the order strings are picked in",
     ];
 
     let selected_options = MultiSelect::with_theme(theme)
@@ -166,8 +478,63 @@ fn get_user_options(theme: &ColorfulTheme) -> Result<(bool, bool, bool, f64, Opt
     let should_sing = selected_options.contains(&0);
     let loop_midi = selected_options.contains(&1);
     let add_another_song = selected_options.contains(&2);
+    let chord_mode = selected_options.contains(&6);
+    let dry_run = selected_options.contains(&7);
+    let per_track_shift = selected_options.contains(&11);
+    let calibrate = selected_options.contains(&18);
+    let interpret_cc = selected_options.contains(&12);
+    let interpret_pitch_bend = selected_options.contains(&40);
+    let vibrato_from_modwheel = selected_options.contains(&36);
+    let skip_percussion = !selected_options.contains(&22);
+    let dedup_tracks = selected_options.contains(&39);
+    let auto_select_tracks = selected_options.contains(&44);
+    let auto_pause_on_blur = selected_options.contains(&24);
+    let on_input_error = if selected_options.contains(&37) {
+        ContinueOrAbort::Continue
+    } else {
+        ContinueOrAbort::Abort
+    };
+    let report_timing = selected_options.contains(&32);
+    let prefer_open_strings = selected_options.contains(&33);
+    let optimize_fingering = selected_options.contains(&34);
+    let contour_singing = selected_options.contains(&42);
+    let allow_octave_substitution = selected_options.contains(&30);
     let mut playback_speed = 1.0;
+    let mut speed_ramp = None;
     let mut start_time: Option<u64> = None;
+    let mut start_bar = None;
+    let mut tuning = STANDARD_TUNING.to_vec();
+    let mut key_map = KeyMap::default();
+    let mut hotkeys = Hotkeys::default();
+    let mut progress_template = None;
+    let mut voice_priority = VoicePriority::default();
+    let mut range_strategy = RangeStrategy::default();
+    let mut shift_weight = ShiftWeight::default();
+    let mut manual_shift: Option<i8> = None;
+    let mut count_in_beats = 0;
+    let mut movement_weight = 0.0;
+    let mut min_velocity = 0;
+    let mut quantize = None;
+    let mut sustain_restrum_ms = None;
+    let mut min_restrum_ms = None;
+    let mut max_fret = MAX_FRET;
+    let mut capo = 0;
+    let mut max_fret_jump = None;
+    let mut max_notes_per_second = None;
+    let mut rate_limit_strategy = RateLimitStrategy::default();
+    let mut pitch_bend_range = DEFAULT_PITCH_BEND_RANGE;
+    let mut disabled_strings = Vec::new();
+    let mut string_preference = Vec::new();
+    let mut loop_section = None;
+    let mut press_hold_ms = None;
+    let mut inter_note_gap_ms = None;
+    let mut scale_sleep_with_speed = false;
+    let mut fret_to_strum_delay_ms = 0;
+    let mut double_strum = false;
+    let mut calibration = Calibration::default();
+    let mut fret_input_mode = FretInputMode::default();
+    let mut fret_button = FretButton::default();
+    let mut humanize = None;
 
     // Playback speed
     if selected_options.contains(&3) {
@@ -177,6 +544,15 @@ fn get_user_options(theme: &ColorfulTheme) -> Result<(bool, bool, bool, f64, Opt
             .interact_text()?;
 
         playback_speed = speed_input.trim().parse().unwrap_or(1.0);
+
+        // Easing into fast speeds gives slow machines time to keep up
+        if playback_speed > 1.0 {
+            let ramp_secs: u64 = Input::with_theme(theme)
+                .with_prompt("Enter seconds to ramp up to that speed (0 for none):")
+                .default(0)
+                .interact_text()?;
+            speed_ramp = (ramp_secs > 0).then(|| Duration::from_secs(ramp_secs));
+        }
     }
 
     // Start time
@@ -185,15 +561,30 @@ fn get_user_options(theme: &ColorfulTheme) -> Result<(bool, bool, bool, f64, Opt
         let now = Local::now();
         let next_minute = now + chrono::Duration::seconds(60 - now.second() as i64);
         let default_time = next_minute.format("%H:%M:%S").to_string();
-        let time_input = Input::with_theme(theme)
-            .with_prompt("Enter start time (HH:MM:SS):")
-            .default(default_time) // Set the default to the next whole minute
-            .interact_text()?;
+        let mut start_datetime = None;
+        loop {
+            let time_input = Input::with_theme(theme)
+                .with_prompt("Enter start time (HH:MM:SS):")
+                .default(default_time.clone()) // Set the default to the next whole minute
+                .interact_text()?;
+
+            let Ok(naive_time) = NaiveTime::parse_from_str(&time_input, "%H:%M:%S") else {
+                println!("Invalid time format. Please use HH:MM:SS.");
+                break;
+            };
+            let current_date = Local::now().date_naive();
+            match current_date.and_time(naive_time).and_local_timezone(Local).single() {
+                Some(datetime) => {
+                    start_datetime = Some(datetime);
+                    break;
+                }
+                // A daylight saving change skips or repeats the time today
+                None => println!("{} doesn't happen exactly once today in the local time zone, enter another time.", time_input),
+            }
+        }
 
-        if let Ok(naive_time) = NaiveTime::parse_from_str(&time_input, "%H:%M:%S") {
-            let current_date = Local::now().date();
-            let start_datetime = current_date.and_time(naive_time);
-            start_time = Some(start_datetime.expect("idk what to put here but otherwise it doesn't work").timestamp() as u64 * 1000);
+        if let Some(start_datetime) = start_datetime {
+            start_time = Some(start_datetime.timestamp() as u64 * 1000);
 
             let delay_input = Input::with_theme(theme)
                 .with_prompt("Enter delay in ms to account for latency (Ping to host):")
@@ -207,11 +598,361 @@ fn get_user_options(theme: &ColorfulTheme) -> Result<(bool, bool, bool, f64, Opt
             } else {
                 println!("Invalid delay input. No delay will be added.");
             }
-        } else {
-            println!("Invalid time format. Please use HH:MM:SS.");
         }
     }
 
+    // Start bar
+    if selected_options.contains(&31) {
+        start_bar = Some(
+            Input::with_theme(theme)
+                .with_prompt("Enter the bar to start playing from:")
+                .default(1)
+                .interact_text()?,
+        );
+    }
+
+    // Guitar tuning
+    if selected_options.contains(&5) {
+        tuning = get_tuning_selection(theme)?;
+    }
+
+    // Keybindings
+    if selected_options.contains(&8) {
+        key_map = get_key_map(theme)?;
+    }
+
+    // Contour singing keys
+    if contour_singing {
+        key_map.sing_up = get_key(theme, "Enter the sing key for a rising melody:", key_map.sing_up)?;
+        key_map.sing_down = get_key(theme, "Enter the sing key for a falling melody:", key_map.sing_down)?;
+    }
+
+    // Hotkeys
+    if selected_options.contains(&25) {
+        hotkeys = get_hotkeys(theme)?;
+    }
+
+    // Progress bar
+    if selected_options.contains(&28) {
+        progress_template = Some(
+            Input::with_theme(theme)
+                .with_prompt("Enter the progress bar template:")
+                .default(PROGRESS_TEMPLATE.to_string())
+                .interact_text()?,
+        );
+    }
+
+    // Voice priority
+    if selected_options.contains(&9) {
+        let priorities = [
+            ("Highest notes", VoicePriority::Highest),
+            ("Lowest notes", VoicePriority::Lowest),
+            ("Melody (track with the highest note)", VoicePriority::Melody),
+        ];
+        let items: Vec<&str> = priorities.iter().map(|(name, _)| *name).collect();
+        let selection = Select::with_theme(theme)
+            .with_prompt("Select which notes to keep when there aren't enough strings")
+            .items(&items)
+            .default(0)
+            .interact()?;
+        voice_priority = priorities[selection].1;
+    }
+
+    // Out of range notes
+    if selected_options.contains(&29) {
+        let strategies = [
+            ("Play the nearest note", RangeStrategy::Clamp),
+            ("Move them by octaves", RangeStrategy::OctaveFold),
+            ("Leave them out", RangeStrategy::Drop),
+        ];
+        let items: Vec<&str> = strategies.iter().map(|(name, _)| *name).collect();
+        let selection = Select::with_theme(theme)
+            .with_prompt("Select what to do with notes the guitar can't reach")
+            .items(&items)
+            .default(0)
+            .interact()?;
+        range_strategy = strategies[selection].1;
+    }
+
+    // Transpose weighting
+    if selected_options.contains(&26) {
+        let weights = [
+            ("All notes equally", ShiftWeight::Uniform),
+            ("Higher notes", ShiftWeight::FavorHigh),
+            // The track is picked once the tracks are selected
+            ("One track", ShiftWeight::FavorTrack(0)),
+        ];
+        let items: Vec<&str> = weights.iter().map(|(name, _)| *name).collect();
+        let selection = Select::with_theme(theme)
+            .with_prompt("Select which notes the transpose should keep in range")
+            .items(&items)
+            .default(0)
+            .interact()?;
+        shift_weight = weights[selection].1;
+    }
+
+    // Manual transpose
+    if selected_options.contains(&10) {
+        let shift_input: String = Input::with_theme(theme)
+            .with_prompt("Enter the number of semitones to transpose by:")
+            .default("0".to_string())
+            .interact_text()?;
+
+        manual_shift = shift_input.trim().parse().ok();
+        if manual_shift.is_none() {
+            println!("Invalid transpose. The shift will be calculated automatically.");
+        }
+    }
+
+    // Count in
+    if selected_options.contains(&13) {
+        count_in_beats = Input::with_theme(theme)
+            .with_prompt("Enter the number of beats to count in:")
+            .default(4)
+            .interact_text()?;
+    }
+
+    // Fret movement weight
+    if selected_options.contains(&14) {
+        let weight_input: String = Input::with_theme(theme)
+            .with_prompt("Enter how strongly to avoid moving frets (0 only picks the least recently used string):")
+            .default("0.5".to_string())
+            .interact_text()?;
+
+        movement_weight = weight_input.trim().parse().unwrap_or(0.0);
+    }
+
+    // Minimum velocity
+    if selected_options.contains(&19) {
+        min_velocity = Input::with_theme(theme)
+            .with_prompt("Enter the lowest velocity to play (0-127):")
+            .default(20)
+            .interact_text()?;
+    }
+
+    // Quantize
+    if selected_options.contains(&20) {
+        quantize = Some(
+            Input::with_theme(theme)
+                .with_prompt("Enter the grid to snap notes to in ticks:")
+                .interact_text()?,
+        );
+    }
+
+    // Re-strum held notes
+    if selected_options.contains(&21) {
+        sustain_restrum_ms = Some(
+            Input::with_theme(theme)
+                .with_prompt("Enter how often to re-strum held notes in ms:")
+                .default(1000)
+                .interact_text()?,
+        );
+    }
+
+    // Repeated note debounce
+    if selected_options.contains(&27) {
+        min_restrum_ms = Some(
+            Input::with_theme(theme)
+                .with_prompt("Enter the least time in ms before the same note is strummed again:")
+                .default(50)
+                .interact_text()?,
+        );
+    }
+
+    // Highest fret
+    if selected_options.contains(&23) {
+        max_fret = Input::with_theme(theme)
+            .with_prompt(format!("Enter the highest fret to play on (0-{}):", MAX_FRET))
+            .default(MAX_FRET)
+            .validate_with(|fret: &i32| -> Result<(), String> {
+                if (0..=MAX_FRET).contains(fret) {
+                    Ok(())
+                } else {
+                    Err(format!("The fret has to be between 0 and {}", MAX_FRET))
+                }
+            })
+            .interact_text()?;
+    }
+
+    // Capo
+    if selected_options.contains(&46) {
+        capo = Input::with_theme(theme)
+            .with_prompt(format!("Enter the fret the capo is on (0-{}):", max_fret))
+            .default(0)
+            .validate_with(|fret: &i32| -> Result<(), String> {
+                if (0..=max_fret).contains(fret) {
+                    Ok(())
+                } else {
+                    Err(format!("The capo has to be between 0 and the highest fret, {}", max_fret))
+                }
+            })
+            .interact_text()?;
+    }
+
+    // Fret jump smoothing
+    if selected_options.contains(&43) {
+        max_fret_jump = Some(
+            Input::with_theme(theme)
+                .with_prompt("Enter the most frets a string can move in one click:")
+                .default(5)
+                .validate_with(|jump: &i32| -> Result<(), String> {
+                    if *jump > 0 {
+                        Ok(())
+                    } else {
+                        Err("The jump has to be at least 1 fret".to_string())
+                    }
+                })
+                .interact_text()?,
+        );
+    }
+
+    // Notes per second limit
+    if selected_options.contains(&45) {
+        max_notes_per_second = Some(
+            Input::with_theme(theme)
+                .with_prompt("Enter the most notes to start in a second:")
+                .default(20)
+                .validate_with(|max: &u32| -> Result<(), String> {
+                    if *max > 0 {
+                        Ok(())
+                    } else {
+                        Err("The limit has to be at least 1 note".to_string())
+                    }
+                })
+                .interact_text()?,
+        );
+        let strategies = [
+            ("Drop the quietest notes", RateLimitStrategy::DropQuietest),
+            ("Play them a little late, dropping the ones waiting longest", RateLimitStrategy::DropOldest),
+            ("Keep the notes on the beat", RateLimitStrategy::ThinToBeat),
+        ];
+        let items: Vec<&str> = strategies.iter().map(|(name, _)| *name).collect();
+        let selection = Select::with_theme(theme)
+            .with_prompt("Select which notes to leave out over the limit")
+            .items(&items)
+            .default(0)
+            .interact()?;
+        rate_limit_strategy = strategies[selection].1;
+    }
+
+    // Pitch bend range
+    if interpret_pitch_bend {
+        pitch_bend_range = Input::with_theme(theme)
+            .with_prompt("Enter how many semitones a full pitch bend moves the note:")
+            .default(DEFAULT_PITCH_BEND_RANGE)
+            .interact_text()?;
+    }
+
+    // Disabled strings
+    if selected_options.contains(&38) {
+        let strings_input: String = Input::with_theme(theme)
+            .with_prompt(format!("Enter the strings to leave unused, 1 is the lowest (1-{}):", tuning.len()))
+            .interact_text()?;
+        disabled_strings = vec![false; tuning.len()];
+        for string in strings_input.split(',').filter_map(|value| value.trim().parse::<usize>().ok()) {
+            match disabled_strings.get_mut(string.wrapping_sub(1)) {
+                Some(disabled) => *disabled = true,
+                None => println!("There is no string {}, ignoring it", string),
+            }
+        }
+    }
+
+    // String preference
+    if selected_options.contains(&47) {
+        let strings_input: String = Input::with_theme(theme)
+            .with_prompt(format!(
+                "Enter the strings to pick first in order, 1 is the lowest (1-{}):",
+                tuning.len()
+            ))
+            .interact_text()?;
+        for string in strings_input.split(',').filter_map(|value| value.trim().parse::<usize>().ok()) {
+            if (1..=tuning.len()).contains(&string) {
+                string_preference.push(string as i32 - 1);
+            } else {
+                println!("There is no string {}, ignoring it", string);
+            }
+        }
+    }
+
+    // A/B loop
+    if selected_options.contains(&15) {
+        let start_input: String = Input::with_theme(theme)
+            .with_prompt("Enter the loop start (MM:SS):")
+            .default("00:00".to_string())
+            .interact_text()?;
+        let end_input: String = Input::with_theme(theme)
+            .with_prompt("Enter the loop end (MM:SS):")
+            .interact_text()?;
+
+        match (parse_song_time(&start_input), parse_song_time(&end_input)) {
+            (Some(start), Some(end)) if start < end => loop_section = Some((start, end)),
+            _ => println!("Invalid loop section. The whole song will be played."),
+        }
+    }
+
+    // Input timing
+    if selected_options.contains(&16) {
+        press_hold_ms = Some(
+            Input::with_theme(theme)
+                .with_prompt("Enter how long to hold keys down in ms:")
+                .interact_text()?,
+        );
+        inter_note_gap_ms = Some(
+            Input::with_theme(theme)
+                .with_prompt("Enter the least time between inputs in ms:")
+                .interact_text()?,
+        );
+        fret_to_strum_delay_ms = Input::with_theme(theme)
+            .with_prompt("Enter how long to wait between setting a fret and strumming it in ms:")
+            .default(0)
+            .interact_text()?;
+        double_strum = dialoguer::Confirm::with_theme(theme)
+            .with_prompt("Strum every note twice in case the game misses the first?")
+            .default(false)
+            .interact()?;
+        scale_sleep_with_speed = dialoguer::Confirm::with_theme(theme)
+            .with_prompt("Shorten these when playing faster than normal speed?")
+            .default(false)
+            .interact()?;
+    }
+
+    // Fretboard calibration
+    if selected_options.contains(&17) {
+        calibration = get_calibration(theme)?;
+    }
+
+    // Fret input mode
+    if selected_options.contains(&35) {
+        fret_input_mode = get_fret_input_mode(theme)?;
+        fret_button = get_fret_button(theme)?;
+    }
+
+    // Humanized timing
+    if selected_options.contains(&41) {
+        let defaults = HumanizeSettings::default();
+        humanize = Some(HumanizeSettings {
+            jitter_ms: Input::with_theme(theme)
+                .with_prompt("Enter how far off the beat notes may land in ms:")
+                .default(defaults.jitter_ms)
+                .interact_text()?,
+            velocity_based: Confirm::with_theme(theme)
+                .with_prompt("Keep loud notes tighter than quiet ones?")
+                .default(true)
+                .interact()?,
+            swing: Input::with_theme(theme)
+                .with_prompt("Enter the amount of swing (0 is straight, 1 is triplet):")
+                .default(defaults.swing)
+                .validate_with(|swing: &f64| {
+                    if (0.0..=1.0).contains(swing) {
+                        Ok(())
+                    } else {
+                        Err("Swing must be between 0 and 1")
+                    }
+                })
+                .interact_text()?,
+        });
+    }
+
     // Check for conflicting options
     if loop_midi && add_another_song {
         let confirm = dialoguer::Confirm::with_theme(theme)
@@ -220,20 +961,348 @@ fn get_user_options(theme: &ColorfulTheme) -> Result<(bool, bool, bool, f64, Opt
             .interact()?;
 
         if confirm {
-            return Ok((should_sing, true, false, playback_speed, start_time))
+            return Ok(UserOptions {
+                should_sing,
+                loop_midi: true,
+                add_another_song: false,
+                playback_speed,
+                speed_ramp,
+                start_time,
+                start_bar,
+                tuning,
+                chord_mode,
+                dry_run,
+                key_map,
+                hotkeys,
+                progress_template,
+                voice_priority,
+                range_strategy,
+                allow_octave_substitution,
+                shift_weight,
+                manual_shift,
+                per_track_shift,
+                interpret_cc,
+                interpret_pitch_bend,
+                pitch_bend_range,
+                vibrato_from_modwheel,
+                count_in_beats,
+                movement_weight,
+                prefer_open_strings,
+                optimize_fingering,
+                min_velocity,
+                quantize,
+                sustain_restrum_ms,
+                min_restrum_ms,
+                skip_percussion,
+                dedup_tracks,
+                auto_select_tracks,
+                max_fret,
+                capo,
+                max_fret_jump,
+                disabled_strings,
+                string_preference,
+                auto_pause_on_blur,
+                on_input_error,
+                report_timing,
+                max_notes_per_second,
+                rate_limit_strategy,
+                loop_section,
+                press_hold_ms,
+                inter_note_gap_ms,
+                scale_sleep_with_speed,
+                fret_to_strum_delay_ms,
+                double_strum,
+                calibration,
+                fret_input_mode,
+                fret_button,
+                humanize,
+                contour_singing,
+                calibrate,
+            });
         } else {
             return get_user_options(theme);
         }
     }
 
-    Ok((should_sing, loop_midi, add_another_song, playback_speed, start_time))
+    Ok(UserOptions {
+        should_sing,
+        loop_midi,
+        add_another_song,
+        playback_speed,
+        speed_ramp,
+        start_time,
+        start_bar,
+        tuning,
+        chord_mode,
+        dry_run,
+        key_map,
+        hotkeys,
+        progress_template,
+        voice_priority,
+        range_strategy,
+        allow_octave_substitution,
+        shift_weight,
+        manual_shift,
+        per_track_shift,
+        interpret_cc,
+        interpret_pitch_bend,
+        pitch_bend_range,
+        vibrato_from_modwheel,
+        count_in_beats,
+        movement_weight,
+        prefer_open_strings,
+        optimize_fingering,
+        min_velocity,
+        quantize,
+        sustain_restrum_ms,
+        min_restrum_ms,
+        skip_percussion,
+        dedup_tracks,
+        auto_select_tracks,
+        max_fret,
+        capo,
+        max_fret_jump,
+        disabled_strings,
+        string_preference,
+        auto_pause_on_blur,
+        on_input_error,
+        report_timing,
+        max_notes_per_second,
+        rate_limit_strategy,
+        loop_section,
+        press_hold_ms,
+        inter_note_gap_ms,
+        scale_sleep_with_speed,
+        fret_to_strum_delay_ms,
+        double_strum,
+        calibration,
+        fret_input_mode,
+        fret_button,
+        humanize,
+        contour_singing,
+        calibrate,
+    })
 }
 
-fn get_window(name: &str) -> Option<Window> {
-    let windows = Window::all().unwrap();
-    windows.into_iter().find(|w| w.app_name() == name)
+fn get_tuning_selection(theme: &ColorfulTheme) -> Result<Vec<u8>, dialoguer::Error> {
+    let presets = [
+        ("Standard (EADGBE)", STANDARD_TUNING),
+        ("Drop D (DADGBE)", [38, 45, 50, 55, 59, 64]),
+        ("Half step down (Eb Ab Db Gb Bb Eb)", [39, 44, 49, 54, 58, 63]),
+    ];
+
+    let mut items: Vec<&str> = presets.iter().map(|(name, _)| *name).collect();
+    items.push("Custom");
+
+    let selection = Select::with_theme(theme)
+        .with_prompt("Select the tuning your in-game guitar uses")
+        .items(&items)
+        .default(0)
+        .interact()?;
+
+    if let Some((_, tuning)) = presets.get(selection) {
+        return Ok(tuning.to_vec());
+    }
+
+    let tuning_input: String = Input::with_theme(theme)
+        .with_prompt("Enter the open MIDI note of each string from low to high:")
+        .default("40,45,50,55,59,64".to_string())
+        .validate_with(|input: &String| -> Result<(), String> {
+            let tuning = parse_tuning(input)?;
+            WebfishingPlayer::validate_tuning(&tuning, MAX_FRET).map_err(|e| e.to_string())
+        })
+        .interact_text()?;
+
+    Ok(parse_tuning(&tuning_input).unwrap_or(STANDARD_TUNING.to_vec()))
 }
 
+// Parses MM:SS into milliseconds
+fn parse_song_time(input: &str) -> Option<u64> {
+    let (mins, secs) = input.trim().split_once(':')?;
+    let mins: u64 = mins.parse().ok()?;
+    let secs: f64 = secs.parse().ok()?;
+    if !(0.0..60.0).contains(&secs) {
+        return None;
+    }
+    Some(mins * 60_000 + (secs * 1000.0) as u64)
+}
+
+fn get_track_shifts(
+    tracks: &[usize],
+    theme: &ColorfulTheme,
+) -> Result<HashMap<usize, i8>, dialoguer::Error> {
+    let mut track_shifts = HashMap::new();
+    for &track in tracks {
+        let shift: i8 = Input::with_theme(theme)
+            .with_prompt(format!("Enter the number of semitones to transpose track {} by:", track))
+            .default(0)
+            .interact_text()?;
+
+        if shift != 0 {
+            track_shifts.insert(track, shift);
+        }
+    }
+
+    Ok(track_shifts)
+}
+
+fn get_fret_input_mode(theme: &ColorfulTheme) -> Result<FretInputMode, dialoguer::Error> {
+    let modes = ["Click", "Press and hold", "Drag"];
+    let selection = Select::with_theme(theme)
+        .with_prompt("Select how to click frets")
+        .items(&modes)
+        .default(0)
+        .interact()?;
+
+    match selection {
+        1 => {
+            let hold_ms: u64 = Input::with_theme(theme)
+                .with_prompt("Enter how long to hold frets down in ms:")
+                .default(50)
+                .interact_text()?;
+            Ok(FretInputMode::PressHold(Duration::from_millis(hold_ms)))
+        }
+        2 => {
+            let offsets_input: String = Input::with_theme(theme)
+                .with_prompt("Enter the drag start and end offsets from the fret in pixels (x1,y1,x2,y2):")
+                .default("0,0,20,0".to_string())
+                .interact_text()?;
+            let offsets: Vec<i32> = offsets_input
+                .split(',')
+                .filter_map(|value| value.trim().parse().ok())
+                .collect();
+            match offsets[..] {
+                [from_x, from_y, to_x, to_y] => Ok(FretInputMode::Drag((from_x, from_y), (to_x, to_y))),
+                _ => {
+                    println!("Invalid offsets. Frets will be clicked.");
+                    Ok(FretInputMode::Click)
+                }
+            }
+        }
+        _ => Ok(FretInputMode::Click),
+    }
+}
+
+fn get_fret_button(theme: &ColorfulTheme) -> Result<FretButton, dialoguer::Error> {
+    let buttons = ["Left", "Right", "Middle"];
+    let selection = Select::with_theme(theme)
+        .with_prompt("Select the mouse button frets are clicked with")
+        .items(&buttons)
+        .default(0)
+        .interact()?;
+
+    Ok(match selection {
+        1 => FretButton::Right,
+        2 => FretButton::Middle,
+        _ => FretButton::Left,
+    })
+}
+
+fn get_calibration(theme: &ColorfulTheme) -> Result<Calibration, dialoguer::Error> {
+    let default = Calibration::default();
+
+    let calibration_input: String = Input::with_theme(theme)
+        .with_prompt(format!(
+            "Enter the fretboard left, top, string spacing and fret spacing in pixels on a {}x{} window:",
+            default.reference_width, default.reference_height
+        ))
+        .default(format!(
+            "{},{},{},{}",
+            default.left, default.top, default.string_spacing, default.fret_spacing
+        ))
+        .interact_text()?;
+
+    let invert_frets = dialoguer::Confirm::with_theme(theme)
+        .with_prompt("Is the open string at the bottom of the fretboard?")
+        .default(false)
+        .interact()?;
+
+    let values: Vec<f32> = calibration_input
+        .split(',')
+        .filter_map(|value| value.trim().parse().ok())
+        .collect();
+    match values[..] {
+        [left, top, string_spacing, fret_spacing] => Ok(Calibration {
+            left,
+            top,
+            string_spacing,
+            fret_spacing,
+            invert_frets,
+            ..default
+        }),
+        _ => {
+            println!("Invalid calibration. The default calibration will be used.");
+            Ok(Calibration { invert_frets, ..default })
+        }
+    }
+}
+
+fn get_hotkeys(theme: &ColorfulTheme) -> Result<Hotkeys, dialoguer::Error> {
+    let default = Hotkeys::default();
+    let ask = |prompt: &str, default: Keycode| -> Result<Keycode, dialoguer::Error> {
+        let input: String = Input::with_theme(theme)
+            .with_prompt(prompt)
+            .default(default.to_string())
+            .validate_with(|input: &String| -> Result<(), String> {
+                input.trim().parse::<Keycode>().map(|_| ())
+            })
+            .interact_text()?;
+        Ok(input.trim().parse().unwrap_or(default))
+    };
+
+    Ok(Hotkeys {
+        quit: ask("Enter the key to stop the song:", default.quit)?,
+        pause: ask("Enter the key to pause and resume:", default.pause)?,
+        start: ask("Enter the key to start playing:", default.start)?,
+    })
+}
+
+fn get_key_map(theme: &ColorfulTheme) -> Result<KeyMap, dialoguer::Error> {
+    let default = KeyMap::default();
+
+    let strings: String = Input::with_theme(theme)
+        .with_prompt("Enter the strum key of each string from low to high:")
+        .default(default.strings.iter().collect())
+        .validate_with(|input: &String| -> Result<(), String> {
+            if input.is_empty() {
+                Err("Expected a key for each string".to_string())
+            } else {
+                Ok(())
+            }
+        })
+        .interact_text()?;
+
+    Ok(KeyMap {
+        strings: strings.chars().collect(),
+        sing: get_key(theme, "Enter the sing key:", default.sing)?,
+        ..default
+    })
+}
+
+fn get_key(theme: &ColorfulTheme, prompt: &str, default: char) -> Result<char, dialoguer::Error> {
+    let key: String = Input::with_theme(theme)
+        .with_prompt(prompt)
+        .default(default.to_string())
+        .validate_with(|input: &String| -> Result<(), String> {
+            match input.chars().count() {
+                1 => Ok(()),
+                count => Err(format!("Expected 1 key, got {}", count)),
+            }
+        })
+        .interact_text()?;
+
+    Ok(key.chars().next().unwrap_or(default))
+}
+
+fn parse_tuning(input: &str) -> Result<Vec<u8>, String> {
+    input
+        .split(',')
+        .map(|note| note.trim().parse::<u8>())
+        .collect::<Result<Vec<u8>, _>>()
+        .map_err(|e| format!("Invalid note: {}", e))
+}
+
+
 fn get_midi_selection(theme: &ColorfulTheme, default_selection: usize) -> (PathBuf, usize) {
     let mut current_dir = PathBuf::from(MIDI_DIR);
 
@@ -243,7 +1312,7 @@ fn get_midi_selection(theme: &ColorfulTheme, default_selection: usize) -> (PathB
         let mut items: Vec<String> = Vec::new();
 
         // Add an option to go to the parent directory
-        if current_dir != PathBuf::from(MIDI_DIR) {
+        if current_dir != Path::new(MIDI_DIR) {
             items.push("..".to_string());
         } else {
             // Replace parent option with refresh in ./midi
@@ -267,9 +1336,9 @@ fn get_midi_selection(theme: &ColorfulTheme, default_selection: usize) -> (PathB
             .interact()
             .unwrap();
 
-        if selection == 0 && current_dir == PathBuf::from(MIDI_DIR) {
+        if selection == 0 && current_dir == Path::new(MIDI_DIR) {
             // Refresh list
-            current_dir = current_dir;
+            continue;
         } else if selection == 0 && current_dir.parent().is_some() {
             // Navigate to the parent folder
             current_dir = current_dir.parent().unwrap().to_path_buf();
@@ -297,8 +1366,8 @@ fn collect_midi_files(dir: &Path) -> (Vec<PathBuf>, Vec<String>) {
                 if let Some(name) = path.file_name().and_then(|n| n.to_str()) {
                     folder_names.push(name.to_string());
                 }
-            } else if path.extension().and_then(|s| s.to_str()) == Some("mid") {
-                // Collect MIDI files
+            } else if path.extension().and_then(|s| s.to_str()) == Some("mid") || is_json_song(&path) {
+                // Collect MIDI files and JSON note lists
                 midi_files.push(path);
             }
         }
@@ -309,6 +1378,10 @@ fn collect_midi_files(dir: &Path) -> (Vec<PathBuf>, Vec<String>) {
     (midi_files, folder_names)
 }
 
+fn is_json_song(path: &Path) -> bool {
+    path.extension().and_then(|s| s.to_str()) == Some("json")
+}
+
 fn get_tracks_from_db(
     midi_path: &str,
     conn: &Connection,
@@ -353,9 +1426,52 @@ fn insert_tracks_to_db(
     Ok(())
 }
 
+fn get_paused_session(
+    midi_path: &str,
+    conn: &Connection,
+) -> Result<Option<PlaybackState>, rusqlite::Error> {
+    let mut stmt = conn.prepare("SELECT tick, loops, elapsed FROM paused_sessions WHERE path = ?1;")?;
+    let result = stmt.query_row([midi_path], |row| {
+        Ok(PlaybackState {
+            tick: row.get::<_, i64>(0)? as u64,
+            loops: row.get(1)?,
+            elapsed_micros: row.get::<_, i64>(2)? as u64,
+        })
+    });
+
+    match result {
+        Ok(state) => Ok(Some(state)),
+        Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+        Err(err) => Err(err),
+    }
+}
+
+fn insert_paused_session(
+    midi_path: &str,
+    state: PlaybackState,
+    conn: &Connection,
+) -> Result<(), rusqlite::Error> {
+    debug!("Saving paused session: {:?}", state);
+
+    conn.execute(
+        "INSERT INTO paused_sessions (path, tick, loops, elapsed)
+        VALUES (?1, ?2, ?3, ?4)
+        ON CONFLICT(path) DO UPDATE SET tick = excluded.tick, loops = excluded.loops, elapsed = excluded.elapsed;",
+        params![midi_path, state.tick as i64, state.loops, state.elapsed_micros as i64],
+    )?;
+
+    Ok(())
+}
+
+fn delete_paused_session(midi_path: &str, conn: &Connection) -> Result<(), rusqlite::Error> {
+    conn.execute("DELETE FROM paused_sessions WHERE path = ?1;", [midi_path])?;
+
+    Ok(())
+}
+
 fn get_tracks_selection(
-    midi_path: &PathBuf,
-    smf: &Smf,
+    midi_path: &Path,
+    source: &dyn EventSource,
     theme: &ColorfulTheme,
     conn: &Connection,
 ) -> Result<Vec<usize>, dialoguer::Error> {
@@ -376,73 +1492,31 @@ fn get_tracks_selection(
         }
     };
 
-    // progams[track] = instrument
-    let mut programs = vec![-1; smf.tracks.len()];
-    // Get the "program"/instrument of each channel
-    for (i, track) in smf.tracks.iter().enumerate() {
-        for event in track {
-            match event.kind {
-                TrackEventKind::Midi { channel, message } => match message {
-                    MidiMessage::ProgramChange { program } => {
-                        debug!(
-                            "Program change: {} - {} channel {} track {}",
-                            program,
-                            INSTRUMENTS[program.as_int() as usize],
-                            channel,
-                            i
-                        );
-                        if channel == 9 {
-                            programs[i] = -128;
-                        } else {
-                            programs[i] = program.as_int() as i8;
-                        }
-                        break;
-                    }
-                    _ => {}
-                },
-                _ => continue,
-            }
-        }
+    let song_info = SongInfo::from_source(source);
+    if let Some(key) = song_info.key_name() {
+        info!("Key: {}", key);
     }
+    if let Some((numerator, denominator)) = song_info.time_signature {
+        info!("Time signature: {}/{}", numerator, denominator);
+    }
+
+    // Single track songs are split up by channel, only list the channels that are used
+    let is_single_track = source.header().format == Format::SingleTrack;
+    let units: Vec<usize> = (0..song_info.tracks.len())
+        .filter(|&unit| !is_single_track || song_info.tracks[unit].note_count > 0)
+        .collect();
 
     // Ask the user which tracks to play
     let mut builder = Builder::new();
-    builder.push_record(["Track #", "Track Name", "Program", "Instrument"]);
-    for (i, track) in smf.tracks.iter().enumerate() {
-        let mut track_name = None;
-        let mut instrument_name = None;
-        let program_number = programs[i];
-        let program_name = if program_number == -128 {
-            // Special case from rhythm channel
-            "Standard Drum Kit"
-        } else {
-            INSTRUMENTS
-                .get(program_number as usize)
-                .unwrap_or(&"Unknown")
-        };
-
-        for event in track {
-            match event.kind {
-                TrackEventKind::Meta(MetaMessage::TrackName(name)) => {
-                    track_name =
-                        Some(str::from_utf8(name).unwrap_or("Failed to decode track name"));
-                }
-                TrackEventKind::Meta(MetaMessage::InstrumentName(name)) => {
-                    instrument_name =
-                        Some(str::from_utf8(name).unwrap_or("Failed to decode instrument name"));
-                }
-                _ => continue,
-            }
-            if track_name.is_some() && instrument_name.is_some() {
-                break;
-            }
-        }
-
+    let unit_header = if is_single_track { "Channel #" } else { "Track #" };
+    builder.push_record([unit_header, "Track Name", "Program", "Instrument"]);
+    for &unit in &units {
+        let track = &song_info.tracks[unit];
         builder.push_record([
-            i.to_string().as_str(),
-            track_name.unwrap_or("Unknown"),
-            program_name,
-            instrument_name.unwrap_or("Unknown"),
+            unit.to_string().as_str(),
+            track.name.as_deref().unwrap_or("Unknown"),
+            track.program_name(),
+            track.instrument_name.as_deref().unwrap_or("Unknown"),
         ]);
     }
     let table = builder.build().with(Style::psql()).to_string();
@@ -451,7 +1525,7 @@ fn get_tracks_selection(
 
     // Check if there is only one track and return it directly if so
     if tracks.len() == 1 {
-        let chosen_track = vec![0];
+        let chosen_track = vec![units[0]];
         if let Err(err) = insert_tracks_to_db(midi_path, &chosen_track, conn) {
             error!("Failed to insert tracks to database: {}", err);
             pause_and_exit(-1);
@@ -460,13 +1534,7 @@ fn get_tracks_selection(
     }
 
     let defaults = if let Some(saved_tracks) = saved_tracks {
-        let mut defaults = vec![false; tracks.len()];
-        for track_index in saved_tracks {
-            if track_index < defaults.len() {
-                defaults[track_index] = true;
-            }
-        }
-        defaults
+        units.iter().map(|unit| saved_tracks.contains(unit)).collect()
     } else {
         vec![true; tracks.len()]
     };
@@ -475,9 +1543,12 @@ fn get_tracks_selection(
         .with_prompt(
             format!("Which tracks to play? (use arrow keys and space to select, enter to confirm)\n  {}\n  {}", tracks_tbl[0], tracks_tbl[1]),
         )
-        .items(&tracks)
+        .items(tracks)
         .defaults(&defaults)
-        .interact()?;
+        .interact()?
+        .into_iter()
+        .map(|selection| units[selection])
+        .collect::<Vec<_>>();
 
     match insert_tracks_to_db(midi_path, &chosen_tracks, conn) {
         Ok(_) => {}
@@ -490,6 +1561,38 @@ fn get_tracks_selection(
     Ok(chosen_tracks)
 }
 
+// Lets the user pick the game window when it isn't found by name, None when there's nothing to pick
+fn get_window_selection(theme: &ColorfulTheme) -> Result<Option<WindowInfo>, Box<dyn std::error::Error>> {
+    let windows: Vec<WindowInfo> = WebfishingPlayer::list_windows()?
+        .into_iter()
+        .filter(|window| window.visible)
+        .collect();
+    if windows.is_empty() {
+        return Ok(None);
+    }
+
+    let items: Vec<String> = windows
+        .iter()
+        .map(|window| {
+            format!(
+                "{} ({}) {}x{}{}",
+                window.title,
+                window.app_name,
+                window.width,
+                window.height,
+                if window.focused { " - focused" } else { "" }
+            )
+        })
+        .collect();
+    let selection = FuzzySelect::with_theme(theme)
+        .with_prompt("Select the game window")
+        .items(&items)
+        .default(0)
+        .interact()?;
+
+    Ok(windows.into_iter().nth(selection))
+}
+
 fn pause_and_exit(code: i32) -> ! {
     println!("Press Enter to exit...");
     let mut input = String::new();
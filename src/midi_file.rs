@@ -0,0 +1,44 @@
+// MIDI files parsed into events that own their data, so the song doesn't borrow the file bytes
+use crate::webfishing_player::{EventSource, TrackNames};
+use midly::{Header, Smf, Track};
+
+/// A parsed MIDI file. Track and instrument names are kept apart from the events,
+/// the byte strings of meta events, SysEx and escapes are left empty since nothing reads them
+#[derive(Debug, Clone)]
+pub struct MidiFile {
+    header: Header,
+    tracks: Vec<Track<'static>>,
+    names: Vec<TrackNames>,
+}
+
+impl MidiFile {
+    pub fn parse(data: &[u8]) -> Result<Self, midly::Error> {
+        let smf = Smf::parse(data)?;
+        let tracks = smf
+            .tracks
+            .iter()
+            .map(|track| track.iter().map(|event| event.to_static()).collect())
+            .collect();
+        let names = smf.tracks.iter().map(|track| TrackNames::from_events(track)).collect();
+
+        Ok(MidiFile {
+            header: smf.header,
+            tracks,
+            names,
+        })
+    }
+}
+
+impl<'a> EventSource<'a> for MidiFile {
+    fn header(&self) -> Header {
+        self.header
+    }
+
+    fn tracks(&self) -> &[Track<'a>] {
+        &self.tracks
+    }
+
+    fn track_names(&self) -> Vec<TrackNames> {
+        self.names.clone()
+    }
+}
@@ -0,0 +1,99 @@
+// Songs that are still being written while they play, sent as events from another thread
+// or read as live MIDI bytes from something like stdin
+use crate::webfishing_player::EventSource;
+use midly::{
+    live::LiveEvent,
+    num::{u15, u28},
+    stream::MidiStream as LiveParser,
+    Format, Header, Timing, Track, TrackEvent, TrackEventKind,
+};
+use std::{
+    io::{ErrorKind, Read},
+    sync::mpsc::{self, Receiver, TryRecvError},
+    thread,
+};
+
+// Live input is played as it arrives, the resolution only matters for the tempo shown
+const LIVE_TICKS_PER_BEAT: u16 = 480;
+const READ_BUFFER_SIZE: usize = 256;
+
+/// A song of one track whose events come in over a channel while it plays.
+/// Events that arrive after their time are played right away
+#[derive(Debug)]
+pub struct MidiStream {
+    receiver: Receiver<TrackEvent<'static>>,
+    ticks_per_beat: u15,
+}
+
+impl MidiStream {
+    /// Plays the events sent on the channel, with delta times in ticks of the given resolution.
+    /// The song ends once the sender is dropped and every event is played
+    pub fn new(receiver: Receiver<TrackEvent<'static>>, ticks_per_beat: u15) -> Self {
+        MidiStream {
+            receiver,
+            ticks_per_beat,
+        }
+    }
+
+    /// Plays live MIDI bytes, like a MIDI input piped into stdin, as soon as they're read.
+    /// The song ends when the reader does
+    pub fn from_reader(mut reader: impl Read + Send + 'static) -> Self {
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            let mut parser = LiveParser::new();
+            let mut buffer = [0; READ_BUFFER_SIZE];
+            loop {
+                let read = match reader.read(&mut buffer) {
+                    Ok(0) => break,
+                    Ok(read) => read,
+                    Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                    Err(_) => break,
+                };
+
+                let mut closed = false;
+                parser.feed(&buffer[..read], |event| {
+                    // System messages have nothing to play
+                    if let LiveEvent::Midi { channel, message } = event {
+                        let event = TrackEvent {
+                            delta: u28::new(0),
+                            kind: TrackEventKind::Midi { channel, message },
+                        };
+                        closed |= sender.send(event).is_err();
+                    }
+                });
+                // Nothing is listening once the player is dropped
+                if closed {
+                    break;
+                }
+            }
+        });
+
+        MidiStream::new(receiver, u15::new(LIVE_TICKS_PER_BEAT))
+    }
+}
+
+impl<'a> EventSource<'a> for MidiStream {
+    fn header(&self) -> Header {
+        Header::new(Format::Parallel, Timing::Metrical(self.ticks_per_beat))
+    }
+
+    fn tracks(&self) -> &[Track<'a>] {
+        &[]
+    }
+
+    fn is_stream(&self) -> bool {
+        true
+    }
+
+    fn poll_events(&self) -> Option<Vec<TrackEvent<'a>>> {
+        let mut events = Vec::new();
+        loop {
+            match self.receiver.try_recv() {
+                Ok(event) => events.push(event),
+                Err(TryRecvError::Empty) => return Some(events),
+                // Hand over the last events before ending the song
+                Err(TryRecvError::Disconnected) => return (!events.is_empty()).then_some(events),
+            }
+        }
+    }
+}
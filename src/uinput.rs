@@ -0,0 +1,136 @@
+// Input for Wayland sessions, where programs can't move the pointer or type into other windows.
+// Keys and clicks go through a virtual keyboard and mouse made with uinput, which the compositor
+// reads like any other device. /dev/uinput has to be writable, usually through the input group
+use libc::{c_char, c_int, c_ulong, input_event, ioctl, uinput_user_dev, O_NONBLOCK};
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, ErrorKind, Write},
+    mem,
+    os::{fd::AsRawFd, unix::fs::OpenOptionsExt},
+    slice,
+    thread::sleep,
+    time::Duration,
+};
+
+// Event types and codes from linux/input-event-codes.h
+const EV_SYN: u16 = 0x00;
+const EV_KEY: u16 = 0x01;
+const EV_ABS: u16 = 0x03;
+const SYN_REPORT: u16 = 0x00;
+const ABS_X: u16 = 0x00;
+const ABS_Y: u16 = 0x01;
+const BUS_VIRTUAL: u16 = 0x06;
+pub const BTN_LEFT: u16 = 0x110;
+pub const BTN_RIGHT: u16 = 0x111;
+pub const BTN_MIDDLE: u16 = 0x112;
+
+// Requests from linux/uinput.h
+const UI_DEV_CREATE: c_ulong = 0x5501;
+const UI_DEV_DESTROY: c_ulong = 0x5502;
+const UI_SET_EVBIT: c_ulong = 0x4004_5564;
+const UI_SET_KEYBIT: c_ulong = 0x4004_5565;
+const UI_SET_ABSBIT: c_ulong = 0x4004_5567;
+
+const DEVICE_NAME: &[u8] = b"webfishing-midi virtual input";
+// The compositor needs a moment to pick up a new device, events sent before that are lost
+const DEVICE_SETTLE: Duration = Duration::from_millis(500);
+
+// Key codes are positions on the keyboard, these are where the characters are on a US layout
+const KEYS: [(char, u16); 47] = [
+    ('1', 2), ('2', 3), ('3', 4), ('4', 5), ('5', 6), ('6', 7), ('7', 8), ('8', 9), ('9', 10), ('0', 11),
+    ('-', 12), ('=', 13), ('q', 16), ('w', 17), ('e', 18), ('r', 19), ('t', 20), ('y', 21), ('u', 22),
+    ('i', 23), ('o', 24), ('p', 25), ('[', 26), (']', 27), ('a', 30), ('s', 31), ('d', 32), ('f', 33),
+    ('g', 34), ('h', 35), ('j', 36), ('k', 37), ('l', 38), (';', 39), ('\'', 40), ('`', 41), ('\\', 43),
+    ('z', 44), ('x', 45), ('c', 46), ('v', 47), ('b', 48), ('n', 49), ('m', 50), (',', 51), ('.', 52),
+    ('/', 53),
+];
+
+/// A virtual keyboard and mouse whose pointer is placed in absolute screen coordinates
+pub struct VirtualInput {
+    device: File,
+}
+
+impl VirtualInput {
+    /// Creates the device, its pointer covers a screen area of the given size from the top left
+    pub fn new(width: i32, height: i32) -> io::Result<Self> {
+        let mut device = OpenOptions::new()
+            .write(true)
+            .custom_flags(O_NONBLOCK)
+            .open("/dev/uinput")?;
+        let fd = device.as_raw_fd();
+
+        for event_type in [EV_SYN, EV_KEY, EV_ABS] {
+            uinput_ioctl(fd, UI_SET_EVBIT, event_type)?;
+        }
+        for code in KEYS.iter().map(|&(_, code)| code).chain([BTN_LEFT, BTN_RIGHT, BTN_MIDDLE]) {
+            uinput_ioctl(fd, UI_SET_KEYBIT, code)?;
+        }
+        for axis in [ABS_X, ABS_Y] {
+            uinput_ioctl(fd, UI_SET_ABSBIT, axis)?;
+        }
+
+        let mut setup: uinput_user_dev = unsafe { mem::zeroed() };
+        for (name, &byte) in setup.name.iter_mut().zip(DEVICE_NAME) {
+            *name = byte as c_char;
+        }
+        setup.id.bustype = BUS_VIRTUAL;
+        setup.id.version = 1;
+        setup.absmax[ABS_X as usize] = width.max(1) - 1;
+        setup.absmax[ABS_Y as usize] = height.max(1) - 1;
+        device.write_all(as_bytes(&setup))?;
+        uinput_ioctl(fd, UI_DEV_CREATE, 0)?;
+
+        sleep(DEVICE_SETTLE);
+        Ok(VirtualInput { device })
+    }
+
+    pub fn move_to(&mut self, x: i32, y: i32) -> io::Result<()> {
+        self.send(&[(EV_ABS, ABS_X, x), (EV_ABS, ABS_Y, y)])
+    }
+
+    pub fn button(&mut self, button: u16, pressed: bool) -> io::Result<()> {
+        self.send(&[(EV_KEY, button, pressed as i32)])
+    }
+
+    pub fn key(&mut self, key: char, pressed: bool) -> io::Result<()> {
+        let key = key.to_ascii_lowercase();
+        let Some(&(_, code)) = KEYS.iter().find(|&&(character, _)| character == key) else {
+            return Err(io::Error::new(
+                ErrorKind::InvalidInput,
+                format!("no key for {:?} on the virtual keyboard", key),
+            ));
+        };
+        self.send(&[(EV_KEY, code, pressed as i32)])
+    }
+
+    // Writes the events followed by the report that makes the compositor apply them together
+    fn send(&mut self, events: &[(u16, u16, i32)]) -> io::Result<()> {
+        for &(event_type, code, value) in events.iter().chain(&[(EV_SYN, SYN_REPORT, 0)]) {
+            let mut event: input_event = unsafe { mem::zeroed() };
+            event.type_ = event_type;
+            event.code = code;
+            event.value = value;
+            self.device.write_all(as_bytes(&event))?;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for VirtualInput {
+    fn drop(&mut self) {
+        // The device goes away with the file anyway, destroying it first is the tidy way
+        let _ = uinput_ioctl(self.device.as_raw_fd(), UI_DEV_DESTROY, 0);
+    }
+}
+
+fn uinput_ioctl(fd: c_int, request: c_ulong, value: u16) -> io::Result<()> {
+    if unsafe { ioctl(fd, request as _, value as c_int) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+// The kernel reads the structs as they're laid out in memory
+fn as_bytes<T>(value: &T) -> &[u8] {
+    unsafe { slice::from_raw_parts(value as *const T as *const u8, mem::size_of::<T>()) }
+}
@@ -1,26 +1,42 @@
+use crate::instruments::INSTRUMENTS;
+use crate::json_song::JsonSong;
+use crate::midi_file::MidiFile;
+use crate::midi_stream::MidiStream;
+#[cfg(all(feature = "wayland", target_os = "linux", not(feature = "silent_input")))]
+use crate::uinput::{self, VirtualInput};
 use device_query::{DeviceQuery, DeviceState, Keycode};
 
 #[cfg(not(feature = "silent_input"))]
 use enigo::{
-    Button, Coordinate,
+    Button, Coordinate, Direction,
     Direction::{Click, Press, Release},
     Enigo, Key, Keyboard, Mouse, Settings,
 };
 
 #[cfg(feature = "silent_input")]
 use enigo::{
+    Coordinate,
     Direction::{Click},
-    Enigo, Key, Keyboard, Settings,
+    Enigo, Key, Keyboard, Mouse, Settings,
 };
 
 use indicatif::{MultiProgress, ProgressBar, ProgressState, ProgressStyle};
 use log::{debug, info, warn};
-use midly::{Format, Smf, TrackEvent, TrackEventKind};
+use serde::{Deserialize, Serialize};
+use midly::{
+    num::u24,
+    Format, Fps, Header, Smf, Track, TrackEvent, TrackEventKind,
+};
 use std::{
-    cmp::Ordering,
-    collections::{BinaryHeap, HashMap},
+    cmp::{Ordering, Reverse},
+    collections::{BTreeMap, HashMap, HashSet, VecDeque},
     fmt::Write,
-    io::Error,
+    error::Error,
+    fs::File,
+    io::{self, Write as _},
+    fmt::Display as FmtDisplay,
+    path::Path,
+    rc::Rc,
     sync::{
         atomic,
         atomic::{AtomicBool, AtomicU64},
@@ -30,24 +46,31 @@ use std::{
     time::{Duration, Instant, SystemTime},
 };
 use xcap::Window;
+#[cfg(all(feature = "wayland", target_os = "linux", not(feature = "silent_input")))]
+use xcap::Monitor;
 
-#[cfg(feature = "silent_input")]
+#[cfg(all(feature = "silent_input", not(windows), not(target_os = "macos")))]
 mod silent_input {
-    pub use std::ptr;
+    pub use libc::{recv, MSG_DONTWAIT, MSG_PEEK};
+    pub use std::{mem::ManuallyDrop, os::raw::c_long, ptr};
     pub use x11::xlib::{Display, *};
 }
 
 // Re-export constants when the feature is enabled
-#[cfg(feature = "silent_input")]
+#[cfg(all(feature = "silent_input", not(windows), not(target_os = "macos")))]
 pub use silent_input::{
     True,
     XFlush,
     XEvent,
+    XKeysymToKeycode,
     XKeyEvent,
     XSendEvent,
     XButtonEvent,
+    XMotionEvent,
     XDefaultRootWindow,
     XOpenDisplay,
+    XCloseDisplay,
+    XConnectionNumber,
     KeyReleaseMask,
     KeyRelease,
     KeyPressMask,
@@ -56,474 +79,4775 @@ pub use silent_input::{
     ButtonPress,
     ButtonReleaseMask,
     ButtonRelease,
+    Button1,
+    Button1Mask,
+    Button1MotionMask,
+    Button2,
+    Button2Mask,
+    Button2MotionMask,
+    Button3,
+    Button3Mask,
+    Button3MotionMask,
+    MotionNotify,
     CurrentTime,
+    c_long,
     ptr,
     Display,
+    ManuallyDrop,
+    recv,
+    MSG_DONTWAIT,
+    MSG_PEEK,
 };
 
-const MIN_NOTE: u8 = 40;
-const MAX_NOTE: u8 = 79;
-
-#[derive(Debug, Eq, PartialEq)]
-struct TimedEvent<'a> {
-    absolute_time: u64,
-    event: TrackEvent<'a>,
-    track: u32,
+// A connection to the X server that reconnects when the server drops it,
+// using a lost connection is undefined behaviour
+#[cfg(all(feature = "silent_input", not(windows), not(target_os = "macos")))]
+struct XDisplay {
+    ptr: *mut Display,
 }
 
-impl<'a> Ord for TimedEvent<'a> {
-    fn cmp(&self, other: &Self) -> Ordering {
-        other.absolute_time.cmp(&self.absolute_time)
+#[cfg(all(feature = "silent_input", not(windows), not(target_os = "macos")))]
+impl XDisplay {
+    fn open() -> Result<Self, PlayerError> {
+        let ptr = unsafe { XOpenDisplay(ptr::null()) };
+        if ptr.is_null() {
+            return Err(PlayerError::OpenDisplay);
+        }
+        Ok(XDisplay { ptr })
     }
-}
 
-impl<'a> PartialOrd for TimedEvent<'a> {
-    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        Some(self.cmp(other))
+    // Peeks at the connection socket without going through Xlib,
+    // which exits the process when it finds the connection closed
+    fn is_alive(&self) -> bool {
+        let mut byte = 0u8;
+        let received = unsafe {
+            recv(
+                XConnectionNumber(self.ptr),
+                &mut byte as *mut u8 as *mut _,
+                1,
+                MSG_PEEK | MSG_DONTWAIT,
+            )
+        };
+        match received {
+            // End of stream, the server closed the connection
+            0 => false,
+            1.. => true,
+            _ => matches!(
+                io::Error::last_os_error().kind(),
+                io::ErrorKind::WouldBlock | io::ErrorKind::Interrupted
+            ),
+        }
     }
-}
 
-#[derive(Clone)]
-pub struct PlayerSettings<'a> {
-    _data: Vec<u8>,
-    pub smf: Smf<'a>,
-    pub loop_midi: bool,
-    pub should_sing: bool,
-    pub sing_above: u8,
-    pub tracks: Option<Vec<usize>>,
-    pub playback_speed: f64,
-    pub start_time: Option<u64>,
+    // The display to send events on, reopened once if the connection was lost
+    fn get(&mut self) -> Result<*mut Display, PlayerError> {
+        if !self.is_alive() {
+            warn!("Lost the connection to the X display, reconnecting");
+            // Closing the lost connection would make Xlib exit, so the old one is left as is
+            let reopened = XDisplay::open()?;
+            self.ptr = ManuallyDrop::new(reopened).ptr;
+        }
+        Ok(self.ptr)
+    }
 }
 
-impl<'a> PlayerSettings<'a> {
-    pub fn new(midi_data: Vec<u8>, loop_midi: bool, should_sing: bool, sing_above: u8, playback_speed: f64, start_time: Option<u64>) -> Result<Self, midly::Error> {
-        let smf = Smf::parse(&midi_data)?;
-        // This is safe because we keep midi_data & smf alive in the struct
-        let smf = unsafe { std::mem::transmute::<Smf<'_>, Smf<'a>>(smf) };
-
-        Ok(PlayerSettings {
-            _data: midi_data,
-            smf,
-            loop_midi,
-            should_sing,
-            sing_above,
-            tracks: None,
-            playback_speed,
-            start_time,
-        })
+#[cfg(all(feature = "silent_input", not(windows), not(target_os = "macos")))]
+impl Drop for XDisplay {
+    fn drop(&mut self) {
+        if self.is_alive() {
+            unsafe { XCloseDisplay(self.ptr) };
+        }
     }
 }
 
-pub struct WebfishingPlayer<'a> {
-    smf: Smf<'a>,
-    shift: i8,
-    micros_per_tick: u64,
-    events: BinaryHeap<TimedEvent<'a>>,
-    enigo: Enigo,
-    window: &'a Window,
-    cur_string_positions: HashMap<i32, i32>,
-    strings_played: [bool; 6],
-    last_string_usage_time: [Instant; 6],
-    input_sleep_duration: u64,
-    loop_midi: bool,
-    wait_for_user: bool,
-    should_sing: bool,
-    sing_above: u8,
-    tracks: Vec<usize>,
-    playback_speed: f64,
-    start_time: Option<u64>,
-    multi: &'a MultiProgress,
-    paused: Arc<AtomicBool>,
-    song_elapsed_micros: Arc<AtomicU64>,
-    _data: Vec<u8>,
-    rshift_pressed: bool,
+#[cfg(not(any(windows, target_os = "macos")))]
+use xcb::{x, Xid};
 
-    #[cfg(feature = "silent_input")]
-    display: *mut Display,
+#[cfg(target_os = "macos")]
+use core_foundation::{
+    base::{CFType, TCFType},
+    dictionary::{CFDictionary, CFDictionaryRef},
+    number::CFNumber,
+    string::CFString,
+};
+#[cfg(target_os = "macos")]
+use core_graphics::window::{
+    copy_window_info, kCGNullWindowID, kCGWindowLayer, kCGWindowListExcludeDesktopElements,
+    kCGWindowListOptionOnScreenOnly, kCGWindowNumber,
+};
+#[cfg(all(feature = "silent_input", target_os = "macos"))]
+use core_graphics::{
+    event::{CGEvent, CGEventType, CGKeyCode, CGMouseButton, EventField},
+    event_source::{CGEventSource, CGEventSourceStateID},
+    geometry::CGPoint,
+    window::{kCGWindowListOptionIncludingWindow, kCGWindowOwnerPID},
+};
+
+#[cfg(windows)]
+use windows_sys::Win32::{Foundation::HWND, UI::WindowsAndMessaging::GetForegroundWindow};
+#[cfg(all(feature = "silent_input", windows))]
+use windows_sys::Win32::{
+    Foundation::{LPARAM, POINT, WPARAM},
+    Graphics::Gdi::ScreenToClient,
+    System::SystemServices::{MK_LBUTTON, MK_MBUTTON, MK_RBUTTON},
+    UI::{
+        Input::KeyboardAndMouse::{MapVirtualKeyW, VkKeyScanW, MAPVK_VK_TO_VSC},
+        WindowsAndMessaging::{
+            PostMessageW, WM_KEYDOWN, WM_KEYUP, WM_LBUTTONDOWN, WM_LBUTTONUP, WM_MBUTTONDOWN,
+            WM_MBUTTONUP, WM_MOUSEMOVE, WM_RBUTTONDOWN, WM_RBUTTONUP,
+        },
+    },
+};
+
+// PostMessageW returns 0 when the message couldn't be posted
+#[cfg(all(feature = "silent_input", windows))]
+unsafe fn post_message(hwnd: HWND, msg: u32, wparam: WPARAM, lparam: LPARAM) -> Result<(), PlayerError> {
+    if PostMessageW(hwnd, msg, wparam, lparam) == 0 {
+        return Err(PlayerError::SendEvent);
+    }
+    Ok(())
 }
 
-struct GuitarPosition {
-    string: i32, // 0-5, where 0 is the lowest E string
-    fret: i32,   // 0 means open string, 1-15 for frets
+// Looks up the virtual key code for the key in the current keyboard layout
+#[cfg(all(feature = "silent_input", windows))]
+fn virtual_key(key: char) -> u16 {
+    let mut utf16 = [0; 2];
+    key.encode_utf16(&mut utf16);
+    let scan = unsafe { VkKeyScanW(utf16[0]) };
+    if scan == -1 {
+        // Not on the keyboard, virtual key codes of letters match their uppercase ASCII
+        return key.to_ascii_uppercase() as u16;
+    }
+    // The low byte is the key code, the high byte the shift state
+    (scan & 0xFF) as u16
 }
 
-impl<'a> WebfishingPlayer<'a> {
-    pub fn new(
-        settings: PlayerSettings<'a>,
-        wait_for_user: bool,
-        input_sleep_duration: u64,
-        window: &'a Window,
-        multi: &'a MultiProgress,
-    ) -> Result<Self, Error> {
-        let smf = settings.smf;
-        if smf.header.format != Format::Parallel {
-            warn!("Format not parallel");
-        }
+// Repeat count of 1 and the scan code of the key message, the game reads both
+#[cfg(all(feature = "silent_input", windows))]
+fn key_lparam(key: u16) -> u32 {
+    let scan_code = unsafe { MapVirtualKeyW(key as u32, MAPVK_VK_TO_VSC) };
+    1 | (scan_code << 16)
+}
 
-        #[cfg(feature = "silent_input")]
-        let display: *mut Display;
+// Finds the process that owns the game window so events can be posted straight to it
+#[cfg(all(feature = "silent_input", target_os = "macos"))]
+fn window_owner_pid(window_id: u32) -> Option<i32> {
+    let windows = copy_window_info(kCGWindowListOptionIncludingWindow, window_id)?;
+    let info = windows.get(0)?;
+    unsafe {
+        let info = CFDictionary::<CFString, CFType>::wrap_under_get_rule(*info as CFDictionaryRef);
+        let key = CFString::wrap_under_get_rule(kCGWindowOwnerPID);
+        info.find(&key)?.downcast::<CFNumber>()?.to_i32()
+    }
+}
 
-        #[cfg(feature = "silent_input")]
-        {
-            display = unsafe { XOpenDisplay(ptr::null()) };
-            if display.is_null() {
-                return Err(Error::new(std::io::ErrorKind::Other, "Failed to open X display"));
+// The frontmost normal window, windows are listed from front to back
+#[cfg(target_os = "macos")]
+fn frontmost_window_id() -> Option<u32> {
+    let windows = copy_window_info(
+        kCGWindowListOptionOnScreenOnly | kCGWindowListExcludeDesktopElements,
+        kCGNullWindowID,
+    )?;
+    unsafe {
+        let layer_key = CFString::wrap_under_get_rule(kCGWindowLayer);
+        let number_key = CFString::wrap_under_get_rule(kCGWindowNumber);
+        for info in windows.iter() {
+            let info = CFDictionary::<CFString, CFType>::wrap_under_get_rule(*info as CFDictionaryRef);
+            // Menu bars and overlays are on higher layers
+            let layer = info
+                .find(&layer_key)
+                .and_then(|layer| layer.downcast::<CFNumber>())
+                .and_then(|layer| layer.to_i32());
+            if layer == Some(0) {
+                let number = info.find(&number_key)?.downcast::<CFNumber>()?.to_i64()?;
+                return Some(number as u32);
             }
         }
+    }
+    None
+}
 
-        let notes = WebfishingPlayer::get_notes(&smf);
-        let shift = WebfishingPlayer::calculate_optimal_shift(&notes);
-        let mut player = WebfishingPlayer {
-            smf,
-            shift,
-            micros_per_tick: 0,
-            events: BinaryHeap::new(),
-            enigo: Enigo::new(&Settings::default()).unwrap(),
-            window,
-            cur_string_positions: HashMap::new(),
-            strings_played: [false; 6],
-            last_string_usage_time: [Instant::now(); 6],
-            input_sleep_duration,
-            loop_midi: settings.loop_midi,
-            wait_for_user,
-            should_sing: settings.should_sing,
-            sing_above: settings.sing_above,
-            tracks: settings.tracks.unwrap_or(Vec::new()),
-            playback_speed: settings.playback_speed,
-            start_time: settings.start_time,
-            multi,
-            paused: Arc::new(AtomicBool::new(false)),
-            song_elapsed_micros: Arc::new(AtomicU64::new(0)),
-            _data: settings._data,
-            rshift_pressed: false,
+// The window with keyboard focus, None when it can't be told
+#[cfg(not(any(windows, target_os = "macos")))]
+fn focused_window_id() -> Option<u32> {
+    let (conn, active_atom) = connect_focus_watch()?;
+    active_window(&conn, active_atom)
+}
 
-            #[cfg(feature = "silent_input")]
-            display,
-        };
+// The window with keyboard focus, None when it can't be told
+#[cfg(windows)]
+fn focused_window_id() -> Option<u32> {
+    let foreground = unsafe { GetForegroundWindow() };
+    Some(foreground as usize as u32)
+}
 
-        // For each 6 strings initialize the cur pos as 0
-        for i in 0..6 {
-            player.cur_string_positions.insert(i, 0);
-        }
+// The window with keyboard focus, None when it can't be told
+#[cfg(target_os = "macos")]
+fn focused_window_id() -> Option<u32> {
+    frontmost_window_id()
+}
 
-        player.prepare_events();
-        Ok(player)
+// On Wayland other programs can't move the pointer or type into the game, so inputs go through
+// a virtual device instead. None outside of Wayland or when the device can't be made
+#[cfg(all(feature = "wayland", target_os = "linux", not(feature = "silent_input")))]
+fn open_virtual_input() -> Option<VirtualInput> {
+    let wayland = std::env::var("XDG_SESSION_TYPE").is_ok_and(|session| session == "wayland")
+        || std::env::var_os("WAYLAND_DISPLAY").is_some();
+    if !wayland {
+        return None;
     }
 
-    fn prepare_events(&mut self) {
-        for (track_num, track) in self.smf.tracks.clone().iter().enumerate() {
-            let should_play = self.tracks.contains(&track_num);
+    // The pointer of the device spans every monitor
+    let monitors = Monitor::all().unwrap_or_default();
+    let width = monitors.iter().map(|monitor| monitor.x() + monitor.width() as i32).max();
+    let height = monitors.iter().map(|monitor| monitor.y() + monitor.height() as i32).max();
+    let (Some(width), Some(height)) = (width, height) else {
+        warn!("Wayland session but no monitors were found, sending inputs the default way");
+        return None;
+    };
 
-            let mut absolute_time = 0;
-            for event in track {
-                absolute_time += event.delta.as_int() as u64;
-                // Skip non-meta events
-                if !should_play && !matches!(event.kind, TrackEventKind::Meta(_)) {
-                    continue;
-                }
-                self.events.push(TimedEvent {
-                    absolute_time,
-                    event: *event,
-                    track: track_num as u32,
-                });
-            }
+    match VirtualInput::new(width, height) {
+        Ok(device) => {
+            info!("Wayland session, sending inputs through a uinput device");
+            Some(device)
+        }
+        Err(e) => {
+            warn!(
+                "Wayland session but the uinput device couldn't be made, sending inputs the default way. \
+                 Make sure /dev/uinput is writable: {}",
+                e
+            );
+            None
         }
     }
+}
 
-    fn find_best_string(&mut self, note: u8) -> Option<GuitarPosition> {
-        let string_notes = [
-            [
-                40, 41, 42, 43, 44, 45, 46, 47, 48, 49, 50, 51, 52, 53, 54, 55,
-            ], // low E
-            [
-                45, 46, 47, 48, 49, 50, 51, 52, 53, 54, 55, 56, 57, 58, 59, 60,
-            ], // A
-            [
-                50, 51, 52, 53, 54, 55, 56, 57, 58, 59, 60, 61, 62, 63, 64, 65,
-            ], // D
-            [
-                55, 56, 57, 58, 59, 60, 61, 62, 63, 64, 65, 66, 67, 68, 69, 70,
-            ], // G
-            [
-                59, 60, 61, 62, 63, 64, 65, 66, 67, 68, 69, 70, 71, 72, 73, 74,
-            ], // B
-            [
-                64, 65, 66, 67, 68, 69, 70, 71, 72, 73, 74, 75, 76, 77, 78, 79,
-            ], // high E
-        ];
+// Whether each step of an enigo direction presses or releases, a click does both
+#[cfg(all(feature = "wayland", target_os = "linux", not(feature = "silent_input")))]
+fn direction_presses(direction: Direction) -> &'static [bool] {
+    match direction {
+        Direction::Press => &[true],
+        Direction::Release => &[false],
+        Direction::Click => &[true, false],
+    }
+}
 
-        let int_note = note as i32;
-        let current_time = Instant::now();
+// Connects to the X server and looks up the atom the window manager keeps the active window in
+#[cfg(not(any(windows, target_os = "macos")))]
+fn connect_focus_watch() -> Option<(xcb::Connection, x::Atom)> {
+    let (conn, _) = xcb::Connection::connect(None).ok()?;
+    let cookie = conn.send_request(&x::InternAtom {
+        only_if_exists: true,
+        name: b"_NET_ACTIVE_WINDOW",
+    });
+    let atom = conn.wait_for_reply(cookie).ok()?.atom();
+    if atom == x::ATOM_NONE {
+        return None;
+    }
+    Some((conn, atom))
+}
 
-        // Create a vector to hold candidates based on last usage time
-        let mut candidates: Vec<(i32, i32)> = Vec::new();
+#[cfg(not(any(windows, target_os = "macos")))]
+fn active_window(conn: &xcb::Connection, active_atom: x::Atom) -> Option<u32> {
+    let root = conn.get_setup().roots().next()?.root();
+    let cookie = conn.send_request(&x::GetProperty {
+        delete: false,
+        window: root,
+        property: active_atom,
+        r#type: x::ATOM_WINDOW,
+        long_offset: 0,
+        long_length: 1,
+    });
+    let reply = conn.wait_for_reply(cookie).ok()?;
+    reply.value::<x::Window>().first().map(|window| window.resource_id())
+}
 
-        for (string_index, notes) in string_notes.iter().enumerate() {
-            if self.strings_played[string_index] {
-                continue; // Skip if this string has already been played
-            }
+// Virtual key codes of the ANSI keyboard layout
+#[cfg(all(feature = "silent_input", target_os = "macos"))]
+fn mac_keycode(key: char) -> Option<CGKeyCode> {
+    let keycode = match key.to_ascii_lowercase() {
+        'a' => 0x00,
+        's' => 0x01,
+        'd' => 0x02,
+        'f' => 0x03,
+        'h' => 0x04,
+        'g' => 0x05,
+        'z' => 0x06,
+        'x' => 0x07,
+        'c' => 0x08,
+        'v' => 0x09,
+        'b' => 0x0B,
+        'q' => 0x0C,
+        'w' => 0x0D,
+        'e' => 0x0E,
+        'r' => 0x0F,
+        'y' => 0x10,
+        't' => 0x11,
+        '1' => 0x12,
+        '2' => 0x13,
+        '3' => 0x14,
+        '4' => 0x15,
+        '6' => 0x16,
+        '5' => 0x17,
+        '=' => 0x18,
+        '9' => 0x19,
+        '7' => 0x1A,
+        '-' => 0x1B,
+        '8' => 0x1C,
+        '0' => 0x1D,
+        ']' => 0x1E,
+        'o' => 0x1F,
+        'u' => 0x20,
+        '[' => 0x21,
+        'i' => 0x22,
+        'p' => 0x23,
+        'l' => 0x25,
+        'j' => 0x26,
+        '\'' => 0x27,
+        'k' => 0x28,
+        ';' => 0x29,
+        '\\' => 0x2A,
+        ',' => 0x2B,
+        '/' => 0x2C,
+        'n' => 0x2D,
+        'm' => 0x2E,
+        '.' => 0x2F,
+        ' ' => 0x31,
+        '`' => 0x32,
+        _ => return None,
+    };
+    Some(keycode)
+}
 
-            if let Some(fret) = notes.iter().position(|&n| n == int_note) {
-                // Found a match, add to candidates
-                candidates.push((string_index as i32, fret.try_into().unwrap()));
-            }
-        }
+// A private source keeps the user's own modifier keys out of the events
+#[cfg(all(feature = "silent_input", target_os = "macos"))]
+fn mac_event_source() -> Result<CGEventSource, PlayerError> {
+    CGEventSource::new(CGEventSourceStateID::Private).map_err(|_| PlayerError::SendEvent)
+}
 
-        // Sort candidates by last usage time (ascending order)
-        candidates.sort_by_key(|&index| {
-            let string_index = index.0 as usize;
-            self.last_string_usage_time[string_index]
-        });
+// Open string plus 15 frets
+const FRET_COUNT: usize = 16;
+// The highest fret on the in-game fretboard
+pub const MAX_FRET: i32 = FRET_COUNT as i32 - 1;
+// How many places in the least recently used order an open string is moved up when preferred
+const OPEN_STRING_BIAS: f64 = 1.5;
 
-        // Select the best candidate (the one with the least last usage time)
-        if let Some(&(string_index, fret)) = candidates.first() {
-            // Update last usage time for the selected string
-            self.last_string_usage_time[string_index as usize] = current_time;
+// 120 BPM, the tempo until the song sets one
+const DEFAULT_TEMPO: u64 = 500_000;
+// The MIDI default pitch bend range in semitones
+pub const DEFAULT_PITCH_BEND_RANGE: f64 = 2.0;
+// Audio preview format and how long a strummed string rings for at most
+const PREVIEW_SAMPLE_RATE: u32 = 44_100;
+const PREVIEW_RING: Duration = Duration::from_millis(1500);
+// Damper pedal
+const SUSTAIN_CONTROLLER: u8 = 64;
+const MODULATION_CONTROLLER: u8 = 1;
+// Fret wobbles on a sung note at full modulation
+const MAX_VIBRATO_CYCLES: usize = 4;
+// Channel 10 is for drums in General MIDI
+const PERCUSSION_CHANNEL: u8 = 9;
 
-            return Some(GuitarPosition {
-                string: string_index,
-                fret,
-            });
-        }
+// The longest the player sleeps between checking inputs
+const POLL_INTERVAL: Duration = Duration::from_millis(5);
+
+// The shortest key hold and input gap scaling with the playback speed goes down to
+const MIN_SCALED_INPUT_SLEEP: Duration = Duration::from_millis(5);
+
+// The smallest game window the fretboard can be clicked on reliably
+const MIN_WINDOW_WIDTH: u32 = 640;
+const MIN_WINDOW_HEIGHT: u32 = 360;
+
+// Share of notes two tracks need in common to count as duplicates of each other
+const DUPLICATE_TRACK_SIMILARITY: f64 = 0.9;
+
+// Inputs sent later than this after their tick count as behind schedule
+const LATE_THRESHOLD: Duration = Duration::from_millis(10);
+
+// Playback speed hotkeys multiply or divide the speed by this step
+const SPEED_STEP: f64 = 1.1;
+const MIN_SPEED: f64 = 0.25;
+const MAX_SPEED: f64 = 4.0;
+
+// Wait before the second strum of a note with double strumming
+const DOUBLE_STRUM_GAP: Duration = Duration::from_millis(30);
+
+// How far the seek hotkeys jump
+const SEEK_STEP_MICROS: i64 = 5_000_000;
+
+// Waiting to start this long without seeing a single key press likely means the keyboard can't be read
+const NO_KEYS_WARNING: Duration = Duration::from_secs(30);
+#[cfg(target_os = "linux")]
+const NO_KEYS_HELP: &str = "No key presses seen yet, the keyboard may not be readable. \
+Hotkeys are read through X11, on Wayland run the game and the player under XWayland";
+#[cfg(target_os = "macos")]
+const NO_KEYS_HELP: &str = "No key presses seen yet, the keyboard may not be readable. \
+Allow your terminal in System Settings > Privacy & Security > Input Monitoring and Accessibility, then restart it";
+#[cfg(windows)]
+const NO_KEYS_HELP: &str = "No key presses seen yet, the keyboard may not be readable. \
+If the game runs as administrator, run the player as administrator too";
+#[cfg(not(any(target_os = "linux", target_os = "macos", windows)))]
+const NO_KEYS_HELP: &str = "No key presses seen yet, the keyboard may not be readable";
+
+// The notes per second limit counts the notes started within this long
+const RATE_WINDOW: Duration = Duration::from_secs(1);
+// Notes held back by the rate limit are dropped after waiting this long
+const MAX_NOTE_WAIT: Duration = Duration::from_millis(100);
+
+// Names the game window goes by, matched against the app name and title in order
+pub const WINDOW_NAMES: [&str; 3] = ["steam_app_3146520", "Fish! (On the WEB!)", "Godot_Engine"];
 
-        None // No suitable string found
+// The progress bar layout, besides the indicatif keys it can use
+// {paused}, {elapsed}, {remaining}, {position} as bar:beat, {speed} and {loop}
+pub const PROGRESS_TEMPLATE: &str =
+    "{paused} [{elapsed} -{remaining}] Bar {position} {wide_bar:.cyan/blue} Speed: {speed}{loop}";
+
+// Open note of each string from the lowest to the highest
+pub const STANDARD_TUNING: [u8; 6] = [40, 45, 50, 55, 59, 64];
+
+/// The in-game keybindings
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct KeyMap {
+    // Strum keys from the lowest to the highest string, at least one per string of the tuning
+    pub strings: Vec<char>,
+    pub sing: char,
+    // Sing keys for a rising and a falling melody, used with contour singing
+    pub sing_up: char,
+    pub sing_down: char,
+}
+
+impl Default for KeyMap {
+    fn default() -> Self {
+        KeyMap {
+            strings: vec!['q', 'w', 'e', 'r', 't', 'y'],
+            sing: 'g',
+            sing_up: 'h',
+            sing_down: 'f',
+        }
     }
+}
 
-    fn is_paused(&self) -> bool {
-        self.paused.load(atomic::Ordering::Relaxed)
+/// The keys that control playback
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Hotkeys {
+    #[serde(with = "keycode_name")]
+    pub quit: Keycode,
+    #[serde(with = "keycode_name")]
+    pub pause: Keycode,
+    // Starts playing when waiting for the user
+    #[serde(with = "keycode_name")]
+    pub start: Keycode,
+}
+
+// Keycodes are stored by name, device_query doesn't support serde
+mod keycode_name {
+    use device_query::Keycode;
+    use serde::{de::Error as _, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(key: &Keycode, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(key)
     }
 
-    fn toggle_pause(&self) {
-        self.paused.fetch_xor(true, atomic::Ordering::Relaxed);
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Keycode, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        name.parse().map_err(D::Error::custom)
     }
+}
 
-    // returns true if the user wants to quit
-    fn check_inputs(&mut self, device_state: &DeviceState) -> bool {
-        let keys = device_state.get_keys();
-        if keys.contains(&Keycode::Escape) {
-            return true;
+impl Default for Hotkeys {
+    fn default() -> Self {
+        Hotkeys {
+            quit: Keycode::Escape,
+            pause: Keycode::RShift,
+            start: Keycode::Backspace,
         }
+    }
+}
 
-        if keys.contains(&Keycode::RShift) {
-            if !self.rshift_pressed {
-                self.toggle_pause();
-                self.rshift_pressed = true;
-            }
-        } else {
-            self.rshift_pressed = false;
-        }
+/// The position and size of the game window, implemented for xcap windows
+/// so the player can also be given fixed dimensions
+pub trait WindowGeometry {
+    fn id(&self) -> u32;
+    fn x(&self) -> i32;
+    fn y(&self) -> i32;
+    fn width(&self) -> u32;
+    fn height(&self) -> u32;
+}
 
+/// Where the events of a song come from, a MIDI file or another format
+/// turned into the same tracks of timed events
+pub trait EventSource<'a> {
+    fn header(&self) -> Header;
+    fn tracks(&self) -> &[Track<'a>];
+
+    /// Whether more events come in through `poll_events()` while the song plays
+    fn is_stream(&self) -> bool {
         false
     }
 
-    pub fn play(&mut self) {
-        let timing = self.smf.header.timing;
-        let ticks_per_beat = match timing {
-            midly::Timing::Metrical(ppq) => ppq.as_int() as u64,
-            _ => unimplemented!("Timecode timing not supported"),
-        };
+    /// The events of a streamed song that arrived since the last poll, in order and
+    /// as one track, None once the song is over
+    fn poll_events(&self) -> Option<Vec<TrackEvent<'a>>> {
+        None
+    }
 
-        let device_state = DeviceState::new();
+    /// The names of each track, sources that don't keep them in the events give them here
+    fn track_names(&self) -> Vec<TrackNames> {
+        self.tracks().iter().map(|track| TrackNames::from_events(track)).collect()
+    }
+}
 
-        println!("Escape to stop the song, right shift to pause/play");
-        if self.wait_for_user {
-            // Attempt to press space in-case the user's OS requires a permission pop-up for input
-            self.enigo.key(Key::Space, Click).unwrap();
+/// The first track and instrument name a track gives itself
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TrackNames {
+    pub name: Option<String>,
+    pub instrument_name: Option<String>,
+}
 
-            #[cfg(feature = "silent_input")]
-            println!("Press backspace to start playing");
-            #[cfg(not(feature = "silent_input"))]
-            println!("Tab over to the game and press backspace to start playing");
-            loop {
-                if device_state.get_keys().contains(&Keycode::Backspace) {
-                    break;
+impl TrackNames {
+    pub fn from_events(track: &[TrackEvent]) -> Self {
+        let mut names = TrackNames::default();
+        for event in track {
+            match event.kind {
+                TrackEventKind::Meta(midly::MetaMessage::TrackName(name)) => {
+                    names.name.get_or_insert_with(|| String::from_utf8_lossy(name).into_owned());
                 }
-            }
-        }
-        else {
-            // Wait to start at a certain timestamp if provided
-            if let Some(start_time) = self.start_time {
-                let current_time = SystemTime::now()
-                    .duration_since(SystemTime::UNIX_EPOCH)
-                    .expect("Time went backwards")
-                    .as_millis();
-
-                if u128::from(start_time) > current_time {
-                    let wait_duration = Duration::from_millis((start_time as u128 - current_time) as u64);
-                    let wait_seconds = wait_duration.as_secs();
-
-                    #[cfg(feature = "silent_input")]
-                    println!("Starting playback in {} seconds...", wait_seconds);
-                    #[cfg(not(feature = "silent_input"))]
-                    println!("Tab over to the game, starting playback in {} seconds...", wait_seconds);
-
-                    std::thread::sleep(wait_duration);
+                TrackEventKind::Meta(midly::MetaMessage::InstrumentName(name)) => {
+                    names.instrument_name.get_or_insert_with(|| String::from_utf8_lossy(name).into_owned());
                 }
+                _ => {}
             }
         }
+        names
+    }
+}
 
-        // Reset the guitar to all open string
-        self.set_fret(6, 0);
+impl<'a> EventSource<'a> for Smf<'a> {
+    fn header(&self) -> Header {
+        self.header
+    }
 
-        let final_tick = self.events.iter().last().unwrap().absolute_time;
-        loop {
-            // Start a new loop for playback
-            let mut last_tick = 0; // Reset last_time for each loop iteration
-            self.song_elapsed_micros.store(0, atomic::Ordering::Relaxed);
+    fn tracks(&self) -> &[Track<'a>] {
+        &self.tracks
+    }
+}
 
-            let pb = self.multi.add(ProgressBar::new(final_tick));
-            let paused = Arc::clone(&self.paused);
-            let elapsed = Arc::clone(&self.song_elapsed_micros);
-            let playback_speed = self.playback_speed;
+impl WindowGeometry for Window {
+    fn id(&self) -> u32 {
+        Window::id(self)
+    }
 
-            pb.set_style(
-                ProgressStyle::with_template("{paused} [{elapsed}] {wide_bar:.cyan/blue} Speed: {speed}")
-                    .unwrap()
-                    .with_key("paused", move |_: &ProgressState, w: &mut dyn Write| {
-                        let ch = if paused.load(atomic::Ordering::Relaxed) {
-                            "⏸"
-                        } else {
-                            "▶"
-                        };
-                        write!(w, "{}", ch).unwrap()
-                    })
-                    .with_key("elapsed", move |_: &ProgressState, w: &mut dyn Write| {
-                        let micros = elapsed.load(atomic::Ordering::Relaxed);
-                        let duration = Duration::from_micros(micros);
-                        let whole_secs = duration.as_secs();
-                        let mins = whole_secs / 60;
-                        let secs = whole_secs % 60;
-                        write!(w, "{:02}:{:02}", mins, secs).unwrap()
-                    })
-                    .with_key("speed", move |_: &ProgressState, w: &mut dyn Write| {
-                        write!(w, "{:.1}x", playback_speed).unwrap()
-                    }),
-            );
+    fn x(&self) -> i32 {
+        Window::x(self)
+    }
 
-            while let Some(timed_event) = self.events.pop() {
-                if self.check_inputs(&device_state) {
-                    info!("Song interrupted");
-                    return;
-                }
-
-                let wait_ticks = timed_event.absolute_time - last_tick;
-                if wait_ticks > 0 {
-                    self.strings_played = [false; 6];
-                    // Sleep for one tick at a time so we can check for escape
-                    // and update the progress bar more smoothly
-                    for current_tick in last_tick..timed_event.absolute_time {
-                        sleep(Duration::from_micros((self.micros_per_tick as f64 / playback_speed) as u64));
-                        pb.set_position(current_tick + 1);
-
-                        // Update elapsed
-                        let new_elapsed = self.song_elapsed_micros.load(atomic::Ordering::Relaxed)
-                            + (self.micros_per_tick as f64 / playback_speed) as u64; // Adjust for playback speed
-                        self.song_elapsed_micros
-                            .store(new_elapsed, atomic::Ordering::Relaxed);
-
-                        // Check for inputs during the wait
-                        if self.check_inputs(&device_state) {
-                            info!("Song interrupted");
-                            return;
-                        }
-                    }
-                }
-                last_tick = timed_event.absolute_time;
+    fn y(&self) -> i32 {
+        Window::y(self)
+    }
 
+    fn width(&self) -> u32 {
+        Window::width(self)
+    }
 
-                // Wait while paused
-                while self.is_paused() {
-                    sleep(Duration::from_millis(100));
-                    if self.check_inputs(&device_state) {
-                        info!("Song interrupted");
-                        return;
-                    }
-                }
+    fn height(&self) -> u32 {
+        Window::height(self)
+    }
+}
 
-                match timed_event.event.kind {
-                    TrackEventKind::Meta(midly::MetaMessage::Tempo(tempo)) => {
-                        self.micros_per_tick = tempo.as_int() as u64 / ticks_per_beat;
-                        info!(
-                            "Tempo change: {}µs per tick - track {}",
-                            self.micros_per_tick, timed_event.track
-                        );
-                    }
-                    TrackEventKind::Midi {
-                        channel: _,
-                        message,
-                    } => match message {
-                        midly::MidiMessage::NoteOn { key, vel } => {
-                            if vel.as_int() > 0 {
-                                let note = (key.as_int() as i8 + self.shift) as u8;
-                                self.play_note(note, timed_event.track);
-
-                                // Update elapsed for the input sleep
-                                let new_elapsed =
-                                    self.song_elapsed_micros.load(atomic::Ordering::Relaxed)
-                                        + self.input_sleep_duration * 1000; // Convert ms to µs
-                                self.song_elapsed_micros
-                                    .store(new_elapsed, atomic::Ordering::Relaxed);
-                            }
-                        }
-                        _ => {}
-                    },
-                    _ => {}
-                }
+/// An open window, listed for picking the game window when it isn't found by name.
+/// It can be played in directly, the position is as it was when listed
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct WindowInfo {
+    pub id: u32,
+    pub title: String,
+    pub app_name: String,
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+    // Whether the window has keyboard focus
+    pub focused: bool,
+    // Minimized and zero sized windows can't be seen or played in
+    pub visible: bool,
+}
 
-                pb.set_position(timed_event.absolute_time as u64);
-            }
+impl WindowInfo {
+    fn new(window: &Window, focused_id: Option<u32>) -> Self {
+        WindowInfo {
+            id: window.id(),
+            title: window.title().to_string(),
+            app_name: window.app_name().to_string(),
+            x: window.x(),
+            y: window.y(),
+            width: window.width(),
+            height: window.height(),
+            focused: focused_id == Some(window.id()),
+            visible: !window.is_minimized() && window.width() > 0 && window.height() > 0,
+        }
+    }
+}
 
-            pb.finish();
-            self.multi.remove(&pb);
+impl From<&Window> for WindowInfo {
+    fn from(window: &Window) -> Self {
+        WindowInfo::new(window, focused_window_id())
+    }
+}
 
-            if self.loop_midi {
-                info!("Looping the MIDI playback (Hold ESC to stop)");
-                self.prepare_events();
-            } else {
-                break;
-            }
-        }
+impl WindowGeometry for WindowInfo {
+    fn id(&self) -> u32 {
+        self.id
     }
 
-    fn play_note(&mut self, note: u8, track: u32) {
-        let note = note.clamp(MIN_NOTE, MAX_NOTE);
+    fn x(&self) -> i32 {
+        self.x
+    }
 
-        // Use the find_best_string function to get the guitar position
-        if let Some(position) = self.find_best_string(note) {
-            info!(
-                "Playing note {} on string {} fret {} - track {}",
-                note,
-                position.string + 1,
-                position.fret,
-                track
-            );
+    fn y(&self) -> i32 {
+        self.y
+    }
 
-            // Set fret position
-            self.set_fret(position.string, position.fret);
+    fn width(&self) -> u32 {
+        self.width
+    }
 
-            // Strum the string
-            self.strum_string(position.string);
+    fn height(&self) -> u32 {
+        self.height
+    }
+}
 
-            self.strings_played[position.string as usize] = true;
-        } else {
-            warn!("No suitable string found for note {}", note);
-        }
+/// Where the fretboard is on screen, measured on a window of the reference size
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Calibration {
+    // Offset from the left where the strings start
+    pub left: f32,
+    // Offset from the top where the frets start
+    pub top: f32,
+    // Distance centre to centre of the strings
+    pub string_spacing: f32,
+    // Distance centre to centre of the frets
+    pub fret_spacing: f32,
+    pub reference_width: f32,
+    pub reference_height: f32,
+    // Fret 0 is at the bottom of the fretboard instead of the top
+    #[serde(default)]
+    pub invert_frets: bool,
+}
 
-        if self.should_sing && note >= self.sing_above {
-            self.sing();
+impl Default for Calibration {
+    fn default() -> Self {
+        Calibration {
+            left: 460.0,
+            top: 130.0,
+            string_spacing: 44.0,
+            fret_spacing: 82.0,
+            reference_width: 2560.0,
+            reference_height: 1440.0,
+            invert_frets: false,
         }
     }
+}
 
-    #[cfg(feature = "silent_input")]
-    fn sing(&self) {
+impl Calibration {
+    /// The screen position of a fret on the given window
+    pub fn fret_position(&self, window: &dyn WindowGeometry, string: i32, fret: i32) -> (i32, i32) {
+        let scale_x = window.width() as f32 / self.reference_width;
+        let scale_y = window.height() as f32 / self.reference_height;
+
+        let scaled_left = (self.left * scale_x) as i32;
+        let scaled_top = (self.top * scale_y) as i32;
+        let scaled_string = (self.string_spacing * scale_x) as i32;
+        let scaled_fret = (self.fret_spacing * scale_y) as i32;
+
+        // Mirrored layouts count the frets up from the bottom
+        let fret_row = if self.invert_frets { MAX_FRET - fret } else { fret };
+
+        let fret_x = window.x() + (scaled_left + (string * scaled_string));
+        let fret_y = window.y() + (scaled_top + (fret_row * scaled_fret));
+        (fret_x, fret_y)
+    }
+}
+
+/// How a fret is clicked, some game UIs miss plain clicks
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FretInputMode {
+    #[default]
+    Click,
+    // Hold the button down on the fret for this long
+    PressHold(Duration),
+    // Press at the first offset from the fret and release at the second, in pixels
+    Drag((i32, i32), (i32, i32)),
+}
+
+impl FretInputMode {
+    // Where to press, how long to hold and where to release for a fret at the position
+    fn points(&self, (x, y): (i32, i32), press_hold: Duration) -> ((i32, i32), Duration, (i32, i32)) {
+        match *self {
+            FretInputMode::Click => ((x, y), Duration::ZERO, (x, y)),
+            FretInputMode::PressHold(hold) => ((x, y), hold, (x, y)),
+            FretInputMode::Drag((from_x, from_y), (to_x, to_y)) => {
+                ((x + from_x, y + from_y), press_hold, (x + to_x, y + to_y))
+            }
+        }
+    }
+}
+
+/// The mouse button frets are clicked with, for when fretting is bound to another button in game
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FretButton {
+    #[default]
+    Left,
+    Right,
+    Middle,
+}
+
+impl FretButton {
+    #[cfg(not(feature = "silent_input"))]
+    fn enigo_button(self) -> Button {
+        match self {
+            FretButton::Left => Button::Left,
+            FretButton::Right => Button::Right,
+            FretButton::Middle => Button::Middle,
+        }
+    }
+
+    #[cfg(all(feature = "wayland", target_os = "linux", not(feature = "silent_input")))]
+    fn uinput_button(self) -> u16 {
+        match self {
+            FretButton::Left => uinput::BTN_LEFT,
+            FretButton::Right => uinput::BTN_RIGHT,
+            FretButton::Middle => uinput::BTN_MIDDLE,
+        }
+    }
+
+    // The X11 button number, its state while held and the mask for motion with it held
+    #[cfg(all(feature = "silent_input", not(windows), not(target_os = "macos")))]
+    fn x11_button(self) -> (u32, u32, c_long) {
+        match self {
+            FretButton::Left => (Button1, Button1Mask, Button1MotionMask),
+            FretButton::Right => (Button3, Button3Mask, Button3MotionMask),
+            FretButton::Middle => (Button2, Button2Mask, Button2MotionMask),
+        }
+    }
+
+    // The button down and up messages and the flag for the button held
+    #[cfg(all(feature = "silent_input", windows))]
+    fn window_messages(self) -> (u32, u32, WPARAM) {
+        match self {
+            FretButton::Left => (WM_LBUTTONDOWN, WM_LBUTTONUP, MK_LBUTTON as WPARAM),
+            FretButton::Right => (WM_RBUTTONDOWN, WM_RBUTTONUP, MK_RBUTTON as WPARAM),
+            FretButton::Middle => (WM_MBUTTONDOWN, WM_MBUTTONUP, MK_MBUTTON as WPARAM),
+        }
+    }
+
+    // The down, dragged and up event types and the button for the events
+    #[cfg(all(feature = "silent_input", target_os = "macos"))]
+    fn mac_events(self) -> (CGEventType, CGEventType, CGEventType, CGMouseButton) {
+        match self {
+            FretButton::Left => (
+                CGEventType::LeftMouseDown,
+                CGEventType::LeftMouseDragged,
+                CGEventType::LeftMouseUp,
+                CGMouseButton::Left,
+            ),
+            FretButton::Right => (
+                CGEventType::RightMouseDown,
+                CGEventType::RightMouseDragged,
+                CGEventType::RightMouseUp,
+                CGMouseButton::Right,
+            ),
+            FretButton::Middle => (
+                CGEventType::OtherMouseDown,
+                CGEventType::OtherMouseDragged,
+                CGEventType::OtherMouseUp,
+                CGMouseButton::Center,
+            ),
+        }
+    }
+}
+
+/// Random timing offsets so strums don't land perfectly on the grid
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct HumanizeSettings {
+    // Standard deviation of the timing offset of each tick in milliseconds
+    pub jitter_ms: f64,
+    // Quiet notes get more of the jitter and loud ones less, like accented notes
+    pub velocity_based: bool,
+    // How far offbeat eighth notes are pushed back, 1 is triplet swing and 0 straight
+    pub swing: f64,
+}
+
+impl Default for HumanizeSettings {
+    fn default() -> Self {
+        HumanizeSettings {
+            jitter_ms: 10.0,
+            velocity_based: false,
+            swing: 0.0,
+        }
+    }
+}
+
+/// Which notes the player sings along with
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SingMode {
+    // Every note at or above the pitch
+    AboveThreshold(u8),
+    // Every note of the track
+    MelodyTrack(usize),
+    // The highest note starting on each tick
+    HighestNote,
+    #[default]
+    Off,
+}
+
+/// Which notes the automatic transpose tries hardest to keep in range
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ShiftWeight {
+    // Every note counts the same
+    #[default]
+    Uniform,
+    // Higher notes count more, they usually carry the melody
+    FavorHigh,
+    // The notes of the track come before all others
+    FavorTrack(usize),
+}
+
+/// Which notes keep their strings when a tick has more notes than the guitar can play
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum VoicePriority {
+    #[default]
+    Highest,
+    Lowest,
+    // Keeps the notes of the track playing the highest note, usually the melody
+    Melody,
+}
+
+/// What happens to notes outside the range of the guitar
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RangeStrategy {
+    // Plays them on the lowest or highest playable note
+    #[default]
+    Clamp,
+    // Moves them by octaves until they fit, keeping the pitch class
+    OctaveFold,
+    // Leaves them out
+    Drop,
+}
+
+/// Which notes give way when a section starts more notes per second than the limit
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RateLimitStrategy {
+    // Drops the quietest notes of the ticks over the limit
+    #[default]
+    DropQuietest,
+    // Holds the notes over the limit back until there's room, dropping the ones waiting longest
+    DropOldest,
+    // Only plays notes on the beat once half the limit is used, so the beat keeps its room
+    ThinToBeat,
+}
+
+/// What happens when an input fails to send during playback
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ContinueOrAbort {
+    // Log the failed input and carry on with the rest of the song
+    Continue,
+    // Stop the song with the error
+    #[default]
+    Abort,
+}
+
+#[derive(Debug)]
+pub enum PlayerError {
+    // Failed to create the enigo connection
+    Connection(enigo::NewConError),
+    // Enigo failed to send an input
+    Input(enigo::InputError),
+    // The uinput device used on Wayland failed to send an input
+    #[cfg(all(feature = "wayland", target_os = "linux", not(feature = "silent_input")))]
+    VirtualInput(io::Error),
+    #[cfg(all(feature = "silent_input", not(windows), not(target_os = "macos")))]
+    OpenDisplay,
+    #[cfg(all(feature = "silent_input", target_os = "macos"))]
+    FindProcess,
+    // The game window didn't accept an event sent to it
+    SendEvent,
+    InvalidTuning(String),
+    // The key map doesn't have a key for every input
+    InvalidKeyMap(String),
+    // The file's timing can't be turned into a tick duration
+    InvalidTiming(String),
+    // None of the selected tracks have notes to play
+    NoPlayableNotes,
+    // Failed to list the open windows
+    ListWindows(xcap::XCapError),
+    // No window matched, has the titles of the open windows
+    WindowNotFound(Vec<String>),
+    // The game window is too small or the fretboard lands outside it
+    InvalidWindow(String),
+    // No track has the name, has the names of the tracks in the song
+    TrackNotFound(String, Vec<String>),
+}
+
+impl FmtDisplay for PlayerError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PlayerError::Connection(e) => write!(f, "Failed to create input connection: {}", e),
+            PlayerError::Input(e) => write!(f, "Failed to send input: {}", e),
+            #[cfg(all(feature = "wayland", target_os = "linux", not(feature = "silent_input")))]
+            PlayerError::VirtualInput(e) => write!(f, "Failed to send input through uinput: {}", e),
+            #[cfg(all(feature = "silent_input", not(windows), not(target_os = "macos")))]
+            PlayerError::OpenDisplay => write!(f, "Failed to open X display"),
+            #[cfg(all(feature = "silent_input", target_os = "macos"))]
+            PlayerError::FindProcess => write!(f, "Failed to find the process of the game window"),
+            PlayerError::SendEvent => write!(f, "Failed to send an event to the game window"),
+            PlayerError::InvalidTuning(reason) => write!(f, "Invalid tuning: {}", reason),
+            PlayerError::InvalidKeyMap(reason) => write!(f, "Invalid key map: {}", reason),
+            PlayerError::InvalidTiming(reason) => write!(f, "Invalid timing: {}", reason),
+            PlayerError::NoPlayableNotes => write!(f, "The selected tracks have no notes to play"),
+            PlayerError::ListWindows(e) => write!(f, "Failed to list windows: {}", e),
+            PlayerError::WindowNotFound(titles) => write!(
+                f,
+                "Could not find the game window, open windows are: {}",
+                titles.join(", ")
+            ),
+            PlayerError::InvalidWindow(reason) => write!(f, "Invalid game window: {}", reason),
+            PlayerError::TrackNotFound(name, names) => write!(
+                f,
+                "No track is named \"{}\", the tracks are: {}",
+                name,
+                names.join(", ")
+            ),
+        }
+    }
+}
+
+impl Error for PlayerError {}
+
+impl From<enigo::NewConError> for PlayerError {
+    fn from(e: enigo::NewConError) -> Self {
+        PlayerError::Connection(e)
+    }
+}
+
+impl From<enigo::InputError> for PlayerError {
+    fn from(e: enigo::InputError) -> Self {
+        PlayerError::Input(e)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+struct TimedEvent<'a> {
+    absolute_time: u64,
+    event: TrackEvent<'a>,
+    track: u32,
+    // Strums the string of the held NoteOn again instead of playing a new note
+    restrum: bool,
+}
+
+impl<'a> TimedEvent<'a> {
+    // Events on the same tick go meta first so tempo applies before the notes,
+    // then releases and other channel events so repeated notes aren't cut short, then notes
+    fn kind_rank(&self) -> u8 {
+        match (self.event.kind, self.note_event()) {
+            (TrackEventKind::Meta(_), _) => 0,
+            (_, Some(NoteEvent::Start { .. })) => 2,
+            _ => 1,
+        }
+    }
+
+    fn note_event(&self) -> Option<NoteEvent> {
+        NoteEvent::from_kind(&self.event.kind)
+    }
+
+    fn key(&self) -> Option<u8> {
+        match self.event.kind {
+            TrackEventKind::Midi {
+                message: midly::MidiMessage::NoteOn { key, .. } | midly::MidiMessage::NoteOff { key, .. },
+                ..
+            } => Some(key.as_int()),
+            _ => None,
+        }
+    }
+}
+
+// A note starting or ending. Sources end notes with either a NoteOff or a NoteOn
+// with 0 velocity, both are read the same so held notes behave the same either way
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum NoteEvent {
+    Start { channel: u8, key: u8, vel: u8 },
+    End { channel: u8, key: u8 },
+}
+
+impl NoteEvent {
+    fn from_kind(kind: &TrackEventKind) -> Option<Self> {
+        let TrackEventKind::Midi { channel, message } = *kind else {
+            return None;
+        };
+        let channel = channel.as_int();
+        match message {
+            midly::MidiMessage::NoteOn { key, vel } if vel.as_int() > 0 => Some(NoteEvent::Start {
+                channel,
+                key: key.as_int(),
+                vel: vel.as_int(),
+            }),
+            midly::MidiMessage::NoteOn { key, .. } | midly::MidiMessage::NoteOff { key, .. } => {
+                Some(NoteEvent::End {
+                    channel,
+                    key: key.as_int(),
+                })
+            }
+            _ => None,
+        }
+    }
+}
+
+impl<'a> Ord for TimedEvent<'a> {
+    // Ties on the tick are broken so the order of a chord and its tempo is the same every run
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.absolute_time
+            .cmp(&other.absolute_time)
+            .then_with(|| self.kind_rank().cmp(&other.kind_rank()))
+            .then_with(|| self.track.cmp(&other.track))
+            .then_with(|| self.key().cmp(&other.key()))
+            .then_with(|| self.restrum.cmp(&other.restrum))
+    }
+}
+
+impl<'a> PartialOrd for TimedEvent<'a> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// The tunable part of `PlayerSettings`, without the song, to keep presets on disk
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub loop_midi: bool,
+    pub sing_mode: SingMode,
+    pub hold_sing: bool,
+    pub contour_singing: bool,
+    pub sing_tracks: Option<Vec<usize>>,
+    pub tracks: Option<Vec<usize>>,
+    pub track_names: Option<Vec<String>>,
+    pub playback_speed: f64,
+    pub speed_ramp: Option<Duration>,
+    pub start_offset_ticks: Option<u64>,
+    pub start_bar: Option<u32>,
+    pub resume_from: Option<PlaybackState>,
+    pub tuning: Vec<u8>,
+    pub chord_mode: bool,
+    pub dry_run: bool,
+    pub key_map: KeyMap,
+    pub hotkeys: Hotkeys,
+    pub progress_template: Option<String>,
+    pub voice_priority: VoicePriority,
+    pub range_strategy: RangeStrategy,
+    pub allow_octave_substitution: bool,
+    pub shift_weight: ShiftWeight,
+    pub manual_shift: Option<i8>,
+    pub track_shifts: HashMap<usize, i8>,
+    pub interpret_cc: bool,
+    pub interpret_pitch_bend: bool,
+    pub pitch_bend_range: f64,
+    pub vibrato_from_modwheel: bool,
+    pub count_in_beats: u8,
+    pub movement_weight: f64,
+    pub prefer_open_strings: bool,
+    pub optimize_fingering: bool,
+    pub min_velocity: u8,
+    pub quantize: Option<u32>,
+    pub sustain_restrum_ms: Option<u64>,
+    pub min_restrum_ms: Option<u64>,
+    pub skip_percussion: bool,
+    pub dedup_tracks: bool,
+    pub auto_select_tracks: bool,
+    pub max_fret: i32,
+    pub capo: i32,
+    pub max_fret_jump: Option<i32>,
+    pub disabled_strings: Vec<bool>,
+    pub string_preference: Vec<i32>,
+    pub auto_pause_on_blur: bool,
+    pub on_input_error: ContinueOrAbort,
+    pub report_timing: bool,
+    pub max_notes_per_second: Option<u32>,
+    pub rate_limit_strategy: RateLimitStrategy,
+    pub loop_start: Option<u64>,
+    pub loop_end: Option<u64>,
+    pub press_hold_ms: Option<u64>,
+    pub inter_note_gap_ms: Option<u64>,
+    pub scale_sleep_with_speed: bool,
+    pub fret_to_strum_delay_ms: u64,
+    pub double_strum: bool,
+    pub calibration: Calibration,
+    pub fret_input_mode: FretInputMode,
+    pub fret_button: FretButton,
+    pub humanize: Option<HumanizeSettings>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            loop_midi: false,
+            sing_mode: SingMode::default(),
+            hold_sing: false,
+            contour_singing: false,
+            sing_tracks: None,
+            tracks: None,
+            track_names: None,
+            playback_speed: 1.0,
+            speed_ramp: None,
+            start_offset_ticks: None,
+            start_bar: None,
+            resume_from: None,
+            tuning: STANDARD_TUNING.to_vec(),
+            chord_mode: false,
+            dry_run: false,
+            key_map: KeyMap::default(),
+            hotkeys: Hotkeys::default(),
+            progress_template: None,
+            voice_priority: VoicePriority::default(),
+            range_strategy: RangeStrategy::default(),
+            allow_octave_substitution: false,
+            shift_weight: ShiftWeight::default(),
+            manual_shift: None,
+            track_shifts: HashMap::new(),
+            interpret_cc: false,
+            interpret_pitch_bend: false,
+            pitch_bend_range: DEFAULT_PITCH_BEND_RANGE,
+            vibrato_from_modwheel: false,
+            count_in_beats: 0,
+            movement_weight: 0.0,
+            prefer_open_strings: false,
+            optimize_fingering: false,
+            min_velocity: 0,
+            quantize: None,
+            sustain_restrum_ms: None,
+            min_restrum_ms: None,
+            skip_percussion: true,
+            dedup_tracks: false,
+            auto_select_tracks: false,
+            max_fret: MAX_FRET,
+            capo: 0,
+            max_fret_jump: None,
+            disabled_strings: Vec::new(),
+            string_preference: Vec::new(),
+            auto_pause_on_blur: false,
+            on_input_error: ContinueOrAbort::default(),
+            report_timing: false,
+            max_notes_per_second: None,
+            rate_limit_strategy: RateLimitStrategy::default(),
+            loop_start: None,
+            loop_end: None,
+            press_hold_ms: None,
+            inter_note_gap_ms: None,
+            scale_sleep_with_speed: false,
+            fret_to_strum_delay_ms: 0,
+            double_strum: false,
+            calibration: Calibration::default(),
+            fret_input_mode: FretInputMode::default(),
+            fret_button: FretButton::default(),
+            humanize: None,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct PlayerSettings<'a> {
+    pub source: Rc<dyn EventSource<'a> + 'a>,
+    pub loop_midi: bool,
+    pub sing_mode: SingMode,
+    // Hold the sing key for as long as the sung note instead of tapping it
+    pub hold_sing: bool,
+    // Sing with the up or down key depending on whether the melody rises or falls
+    pub contour_singing: bool,
+    // Only notes of these tracks can sing, None lets every track sing
+    pub sing_tracks: Option<Vec<usize>>,
+    pub tracks: Option<Vec<usize>>,
+    // Tracks to play by their names in the song, added to the numbered tracks
+    pub track_names: Option<Vec<String>>,
+    pub playback_speed: f64,
+    // Ramp up from normal speed to the playback speed over this long at the start of the song,
+    // so the game isn't flooded with inputs before it keeps up
+    pub speed_ramp: Option<Duration>,
+    pub start_time: Option<u64>,
+    // Tick to start the song from instead of the beginning, after waiting for the start time
+    pub start_offset_ticks: Option<u64>,
+    // Bar to start the song from, counted from 1 through every time signature change.
+    // Used in place of the start offset, timecode songs have no bars and start at the beginning
+    pub start_bar: Option<u32>,
+    // Where an interrupted playback stopped, picked up instead of the start offset
+    pub resume_from: Option<PlaybackState>,
+    pub tuning: Vec<u8>,
+    pub chord_mode: bool,
+    pub dry_run: bool,
+    pub key_map: KeyMap,
+    pub hotkeys: Hotkeys,
+    // Layout of the progress bar, None uses PROGRESS_TEMPLATE
+    pub progress_template: Option<String>,
+    pub voice_priority: VoicePriority,
+    pub range_strategy: RangeStrategy,
+    // Play a note an octave away when every string that could play it is taken on the tick
+    pub allow_octave_substitution: bool,
+    pub shift_weight: ShiftWeight,
+    // Semitones to transpose by instead of the automatically calculated shift
+    pub manual_shift: Option<i8>,
+    // Semitones to transpose each track by on top of the global shift
+    pub track_shifts: HashMap<usize, i8>,
+    // Follow the sustain pedal instead of ignoring it
+    pub interpret_cc: bool,
+    // Slide the sounding notes of a channel along with its pitch bends
+    pub interpret_pitch_bend: bool,
+    // Semitones a full pitch bend moves the note by
+    pub pitch_bend_range: f64,
+    // Wobble the fret of sung notes while the modulation wheel is up
+    pub vibrato_from_modwheel: bool,
+    // Beats to count in before the first note, 0 to start right away
+    pub count_in_beats: u8,
+    // How much moving the fret hand is avoided compared to reusing the same string, 0 ignores movement
+    pub movement_weight: f64,
+    // Lean towards open strings, they only need a strum and no fret change
+    pub prefer_open_strings: bool,
+    // Plan the strings of the whole song up front for the fewest fret changes,
+    // slower to prepare than picking a string as each note comes
+    pub optimize_fingering: bool,
+    // Notes quieter than this are skipped to leave the strings for louder ones
+    pub min_velocity: u8,
+    // Grid in ticks to snap note timings to, None keeps them as they are
+    pub quantize: Option<u32>,
+    // Strum held notes again after this many milliseconds so they keep ringing
+    pub sustain_restrum_ms: Option<u64>,
+    // The least time before a string is strummed again on the same fret, None strums every note
+    pub min_restrum_ms: Option<u64>,
+    // Leave out the General MIDI percussion channel
+    pub skip_percussion: bool,
+    // Leave out tracks that play nearly the same notes as another chosen track
+    pub dedup_tracks: bool,
+    // Play the guitar tracks and the melody when no tracks are chosen
+    pub auto_select_tracks: bool,
+    // The highest fret notes can be played on, lower for a shorter fretboard
+    pub max_fret: i32,
+    // Raises every string by this many frets, frets are counted from the capo and the ones
+    // below it aren't played
+    pub capo: i32,
+    // Click the frets in between when a string moves further than this, for when the game
+    // animates the slide and misses a far jump. None moves straight to the fret
+    pub max_fret_jump: Option<i32>,
+    // Strings that are never used, from the lowest string, missing strings are used
+    pub disabled_strings: Vec<bool>,
+    // Strings to pick first when several can play a note, from 0 for the lowest string.
+    // Strings that aren't listed come after, empty picks the least recently used string
+    pub string_preference: Vec<i32>,
+    // Pause while the game window isn't focused so inputs don't go to other windows
+    pub auto_pause_on_blur: bool,
+    // Whether a failed input stops the song or only that input is skipped
+    pub on_input_error: ContinueOrAbort,
+    // Log how far behind schedule the inputs fell when the song ends
+    pub report_timing: bool,
+    // Start at most this many notes a second so dense sections don't put playback behind,
+    // the strategy picks the notes left out. None plays every note
+    pub max_notes_per_second: Option<u32>,
+    pub rate_limit_strategy: RateLimitStrategy,
+    // Section to loop in milliseconds from the start of the song, used when both are set
+    pub loop_start: Option<u64>,
+    pub loop_end: Option<u64>,
+    // How long keys are held down and the least time between inputs,
+    // None uses the input sleep duration the player is created with
+    pub press_hold_ms: Option<u64>,
+    pub inter_note_gap_ms: Option<u64>,
+    // Shorten the key hold and input gap as the song speeds up so fast sections keep up
+    pub scale_sleep_with_speed: bool,
+    // Wait between setting a fret and strumming it, for when the game is slow to register the fret
+    pub fret_to_strum_delay_ms: u64,
+    // Strum every note a second time shortly after the first, for when the game misses strums at low FPS
+    pub double_strum: bool,
+    pub calibration: Calibration,
+    // How frets are clicked, a plain click unless the game misses them
+    pub fret_input_mode: FretInputMode,
+    // The mouse button frets are clicked with
+    pub fret_button: FretButton,
+    // Random offsets from the exact timing of each tick, None plays on time
+    pub humanize: Option<HumanizeSettings>,
+}
+
+impl<'a> PlayerSettings<'a> {
+    pub fn new(midi_data: Vec<u8>, loop_midi: bool, sing_mode: SingMode, playback_speed: f64, start_time: Option<u64>) -> Result<Self, midly::Error> {
+        let mut settings = PlayerSettings::builder()
+            .loop_midi(loop_midi)
+            .sing_mode(sing_mode)
+            .playback_speed(playback_speed)
+            .build(midi_data)?;
+        settings.start_time = start_time;
+        Ok(settings)
+    }
+
+    pub fn builder() -> PlayerSettingsBuilder {
+        PlayerSettingsBuilder::new()
+    }
+
+    /// Settings for the MIDI file from a saved preset
+    pub fn from_config(config: Config, midi_data: Vec<u8>) -> Result<Self, midly::Error> {
+        let song = MidiFile::parse(&midi_data)?;
+        Ok(PlayerSettings::with_source(Rc::new(song), config))
+    }
+
+    /// Settings for a song given as a JSON note list instead of a MIDI file
+    pub fn from_json(json_data: &[u8], loop_midi: bool, sing_mode: SingMode, playback_speed: f64, start_time: Option<u64>) -> Result<Self, serde_json::Error> {
+        let mut settings = PlayerSettings::builder()
+            .loop_midi(loop_midi)
+            .sing_mode(sing_mode)
+            .playback_speed(playback_speed)
+            .build_json(json_data)?;
+        settings.start_time = start_time;
+        Ok(settings)
+    }
+
+    fn with_source(source: Rc<dyn EventSource<'a> + 'a>, config: Config) -> Self {
+        PlayerSettings {
+            source,
+            loop_midi: config.loop_midi,
+            sing_mode: config.sing_mode,
+            hold_sing: config.hold_sing,
+            contour_singing: config.contour_singing,
+            sing_tracks: config.sing_tracks,
+            tracks: config.tracks,
+            track_names: config.track_names,
+            playback_speed: config.playback_speed,
+            speed_ramp: config.speed_ramp,
+            start_time: None,
+            start_offset_ticks: config.start_offset_ticks,
+            start_bar: config.start_bar,
+            resume_from: config.resume_from,
+            tuning: config.tuning,
+            chord_mode: config.chord_mode,
+            dry_run: config.dry_run,
+            key_map: config.key_map,
+            hotkeys: config.hotkeys,
+            progress_template: config.progress_template,
+            voice_priority: config.voice_priority,
+            range_strategy: config.range_strategy,
+            allow_octave_substitution: config.allow_octave_substitution,
+            shift_weight: config.shift_weight,
+            manual_shift: config.manual_shift,
+            track_shifts: config.track_shifts,
+            interpret_cc: config.interpret_cc,
+            interpret_pitch_bend: config.interpret_pitch_bend,
+            pitch_bend_range: config.pitch_bend_range,
+            vibrato_from_modwheel: config.vibrato_from_modwheel,
+            count_in_beats: config.count_in_beats,
+            movement_weight: config.movement_weight,
+            prefer_open_strings: config.prefer_open_strings,
+            optimize_fingering: config.optimize_fingering,
+            min_velocity: config.min_velocity,
+            quantize: config.quantize,
+            sustain_restrum_ms: config.sustain_restrum_ms,
+            min_restrum_ms: config.min_restrum_ms,
+            skip_percussion: config.skip_percussion,
+            dedup_tracks: config.dedup_tracks,
+            auto_select_tracks: config.auto_select_tracks,
+            max_fret: config.max_fret,
+            capo: config.capo,
+            max_fret_jump: config.max_fret_jump,
+            disabled_strings: config.disabled_strings,
+            string_preference: config.string_preference,
+            auto_pause_on_blur: config.auto_pause_on_blur,
+            on_input_error: config.on_input_error,
+            report_timing: config.report_timing,
+            max_notes_per_second: config.max_notes_per_second,
+            rate_limit_strategy: config.rate_limit_strategy,
+            loop_start: config.loop_start,
+            loop_end: config.loop_end,
+            press_hold_ms: config.press_hold_ms,
+            inter_note_gap_ms: config.inter_note_gap_ms,
+            scale_sleep_with_speed: config.scale_sleep_with_speed,
+            fret_to_strum_delay_ms: config.fret_to_strum_delay_ms,
+            double_strum: config.double_strum,
+            calibration: config.calibration,
+            fret_input_mode: config.fret_input_mode,
+            fret_button: config.fret_button,
+            humanize: config.humanize,
+        }
+    }
+
+    /// The tunable settings to save as a preset
+    pub fn config(&self) -> Config {
+        Config {
+            loop_midi: self.loop_midi,
+            sing_mode: self.sing_mode,
+            hold_sing: self.hold_sing,
+            contour_singing: self.contour_singing,
+            sing_tracks: self.sing_tracks.clone(),
+            tracks: self.tracks.clone(),
+            track_names: self.track_names.clone(),
+            playback_speed: self.playback_speed,
+            speed_ramp: self.speed_ramp,
+            start_offset_ticks: self.start_offset_ticks,
+            start_bar: self.start_bar,
+            resume_from: self.resume_from,
+            tuning: self.tuning.clone(),
+            chord_mode: self.chord_mode,
+            dry_run: self.dry_run,
+            key_map: self.key_map.clone(),
+            hotkeys: self.hotkeys,
+            progress_template: self.progress_template.clone(),
+            voice_priority: self.voice_priority,
+            range_strategy: self.range_strategy,
+            allow_octave_substitution: self.allow_octave_substitution,
+            shift_weight: self.shift_weight,
+            manual_shift: self.manual_shift,
+            track_shifts: self.track_shifts.clone(),
+            interpret_cc: self.interpret_cc,
+            interpret_pitch_bend: self.interpret_pitch_bend,
+            pitch_bend_range: self.pitch_bend_range,
+            vibrato_from_modwheel: self.vibrato_from_modwheel,
+            count_in_beats: self.count_in_beats,
+            movement_weight: self.movement_weight,
+            prefer_open_strings: self.prefer_open_strings,
+            optimize_fingering: self.optimize_fingering,
+            min_velocity: self.min_velocity,
+            quantize: self.quantize,
+            sustain_restrum_ms: self.sustain_restrum_ms,
+            min_restrum_ms: self.min_restrum_ms,
+            skip_percussion: self.skip_percussion,
+            dedup_tracks: self.dedup_tracks,
+            auto_select_tracks: self.auto_select_tracks,
+            max_fret: self.max_fret,
+            capo: self.capo,
+            max_fret_jump: self.max_fret_jump,
+            disabled_strings: self.disabled_strings.clone(),
+            string_preference: self.string_preference.clone(),
+            auto_pause_on_blur: self.auto_pause_on_blur,
+            on_input_error: self.on_input_error,
+            report_timing: self.report_timing,
+            max_notes_per_second: self.max_notes_per_second,
+            rate_limit_strategy: self.rate_limit_strategy,
+            loop_start: self.loop_start,
+            loop_end: self.loop_end,
+            press_hold_ms: self.press_hold_ms,
+            inter_note_gap_ms: self.inter_note_gap_ms,
+            scale_sleep_with_speed: self.scale_sleep_with_speed,
+            fret_to_strum_delay_ms: self.fret_to_strum_delay_ms,
+            double_strum: self.double_strum,
+            calibration: self.calibration,
+            fret_input_mode: self.fret_input_mode,
+            fret_button: self.fret_button,
+            humanize: self.humanize,
+        }
+    }
+}
+
+// Chained setters for the fields of the config, optional fields take the value to set them to
+macro_rules! config_setters {
+    ($($field:ident: $type:ty),* $(,)?; $($optional:ident: $optional_type:ty),* $(,)?) => {
+        $(
+            pub fn $field(mut self, $field: $type) -> Self {
+                self.config.$field = $field;
+                self
+            }
+        )*
+        $(
+            pub fn $optional(mut self, $optional: $optional_type) -> Self {
+                self.config.$optional = Some($optional);
+                self
+            }
+        )*
+    };
+}
+
+/// Builds `PlayerSettings` with defaults for every option that isn't set
+#[derive(Debug, Clone, Default)]
+pub struct PlayerSettingsBuilder {
+    config: Config,
+    start_time: Option<u64>,
+}
+
+impl PlayerSettingsBuilder {
+    pub fn new() -> Self {
+        PlayerSettingsBuilder::default()
+    }
+
+    /// Starts from a saved preset instead of the defaults
+    pub fn config(mut self, config: Config) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Unix time in milliseconds to start playing at
+    pub fn start_time(mut self, start_time: u64) -> Self {
+        self.start_time = Some(start_time);
+        self
+    }
+
+    config_setters! {
+        loop_midi: bool,
+        sing_mode: SingMode,
+        hold_sing: bool,
+        contour_singing: bool,
+        playback_speed: f64,
+        tuning: Vec<u8>,
+        chord_mode: bool,
+        dry_run: bool,
+        key_map: KeyMap,
+        hotkeys: Hotkeys,
+        voice_priority: VoicePriority,
+        range_strategy: RangeStrategy,
+        allow_octave_substitution: bool,
+        shift_weight: ShiftWeight,
+        track_shifts: HashMap<usize, i8>,
+        interpret_cc: bool,
+        interpret_pitch_bend: bool,
+        pitch_bend_range: f64,
+        vibrato_from_modwheel: bool,
+        count_in_beats: u8,
+        movement_weight: f64,
+        prefer_open_strings: bool,
+        optimize_fingering: bool,
+        min_velocity: u8,
+        skip_percussion: bool,
+        dedup_tracks: bool,
+        auto_select_tracks: bool,
+        max_fret: i32,
+        capo: i32,
+        disabled_strings: Vec<bool>,
+        string_preference: Vec<i32>,
+        auto_pause_on_blur: bool,
+        on_input_error: ContinueOrAbort,
+        report_timing: bool,
+        rate_limit_strategy: RateLimitStrategy,
+        scale_sleep_with_speed: bool,
+        fret_to_strum_delay_ms: u64,
+        double_strum: bool,
+        calibration: Calibration,
+        fret_input_mode: FretInputMode,
+        fret_button: FretButton;
+        sing_tracks: Vec<usize>,
+        tracks: Vec<usize>,
+        track_names: Vec<String>,
+        speed_ramp: Duration,
+        start_offset_ticks: u64,
+        start_bar: u32,
+        resume_from: PlaybackState,
+        progress_template: String,
+        manual_shift: i8,
+        quantize: u32,
+        sustain_restrum_ms: u64,
+        min_restrum_ms: u64,
+        loop_start: u64,
+        loop_end: u64,
+        press_hold_ms: u64,
+        inter_note_gap_ms: u64,
+        humanize: HumanizeSettings,
+        max_fret_jump: i32,
+        max_notes_per_second: u32,
+    }
+
+    /// Settings for the MIDI file
+    pub fn build<'a>(self, midi_data: Vec<u8>) -> Result<PlayerSettings<'a>, midly::Error> {
+        let mut settings = PlayerSettings::from_config(self.config, midi_data)?;
+        settings.start_time = self.start_time;
+        Ok(settings)
+    }
+
+    /// Settings for a song given as a JSON note list
+    pub fn build_json<'a>(self, json_data: &[u8]) -> Result<PlayerSettings<'a>, serde_json::Error> {
+        let song = JsonSong::parse(json_data)?;
+        let mut settings = PlayerSettings::with_source(Rc::new(song), self.config);
+        settings.start_time = self.start_time;
+        Ok(settings)
+    }
+
+    /// Settings for a song streamed in while it plays. Track selection picks the channels
+    /// to play, the rest of the song isn't known ahead so nothing is transposed or planned
+    pub fn build_stream<'a>(self, stream: MidiStream) -> PlayerSettings<'a> {
+        let mut settings = PlayerSettings::with_source(Rc::new(stream), self.config);
+        settings.start_time = self.start_time;
+        settings
+    }
+}
+
+/// A summary of how much of the song fits on the guitar
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PlayabilityReport {
+    pub total_notes: usize,
+    pub playable_notes: usize,
+    // Notes outside the guitar range that get clamped to the nearest playable note
+    pub clamped_notes: usize,
+    pub shift: i8,
+    // Unplayable notes by the MIDI octave of their unshifted key
+    pub unplayable_octaves: [usize; 11],
+}
+
+/// Where playback was when it was interrupted, saved to pick the song up again later
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PlaybackState {
+    pub tick: u64,
+    // How many times the song or its loop section had been played through
+    pub loops: u32,
+    // The song time at the tick, for showing where it resumes
+    pub elapsed_micros: u64,
+}
+
+/// How far behind schedule the inputs were sent during the last playback
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TimingReport {
+    // Ticks that had inputs to send
+    pub input_ticks: u64,
+    // Ticks whose inputs were sent later than LATE_THRESHOLD
+    pub late_ticks: u64,
+    pub total_lateness: Duration,
+    pub max_lateness: Duration,
+}
+
+impl TimingReport {
+    pub fn average_lateness(&self) -> Duration {
+        if self.input_ticks == 0 {
+            return Duration::ZERO;
+        }
+        Duration::from_micros((self.total_lateness.as_micros() / self.input_ticks as u128) as u64)
+    }
+
+    fn record(&mut self, lateness: Duration) {
+        self.input_ticks += 1;
+        if lateness > LATE_THRESHOLD {
+            self.late_ticks += 1;
+        }
+        self.total_lateness += lateness;
+        self.max_lateness = self.max_lateness.max(lateness);
+    }
+}
+
+/// Why a note couldn't be played
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MappingReason {
+    // Outside the guitar range with the drop range strategy
+    OutOfRange,
+    // Every string that could play it was taken
+    StringsExhausted,
+    // A drum note with percussion skipped
+    Percussion,
+    // Over the notes per second limit
+    RateLimited,
+}
+
+/// A note dropped during playback
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MappingIssue {
+    pub note: u8,
+    pub tick: u64,
+    pub track: u32,
+    pub reason: MappingReason,
+}
+
+/// Metadata of a track for showing in track selection
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TrackInfo {
+    pub name: Option<String>,
+    pub instrument_name: Option<String>,
+    // The first program change of the track
+    pub program: Option<u8>,
+    // Whether the track plays on the rhythm channel
+    pub is_drums: bool,
+    pub note_count: usize,
+}
+
+impl TrackInfo {
+    pub fn program_name(&self) -> &'static str {
+        if self.is_drums {
+            return "Standard Drum Kit";
+        }
+        match self.program {
+            Some(program) => INSTRUMENTS[program as usize],
+            None => "Unknown",
+        }
+    }
+}
+
+/// Metadata of the song for frontends
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SongInfo {
+    pub tracks: Vec<TrackInfo>,
+    // Number of sharps (positive) or flats (negative) and whether the key is minor
+    pub key_signature: Option<(i8, bool)>,
+    // Beats per bar and the note value of a beat
+    pub time_signature: Option<(u8, u8)>,
+}
+
+impl SongInfo {
+    /// Reads the metadata of each track, or of each channel for single track songs
+    pub fn from_source(source: &dyn EventSource) -> Self {
+        let format = source.header().format;
+        let is_single_track = format == Format::SingleTrack;
+        let mut info = SongInfo {
+            tracks: vec![TrackInfo::default(); if is_single_track { 16 } else { source.tracks().len() }],
+            ..SongInfo::default()
+        };
+        for (track_num, track) in source.tracks().iter().enumerate() {
+            for event in track {
+                let track_info = &mut info.tracks[selection_unit(format, track_num, &event.kind)];
+                match event.kind {
+                    TrackEventKind::Meta(midly::MetaMessage::KeySignature(sharps, minor)) => {
+                        info.key_signature.get_or_insert((sharps, minor));
+                    }
+                    TrackEventKind::Meta(midly::MetaMessage::TimeSignature(numerator, power, ..)) => {
+                        // The denominator is stored as a power of 2
+                        let denominator = 1u8.checked_shl(power as u32).unwrap_or(0);
+                        info.time_signature.get_or_insert((numerator, denominator));
+                    }
+                    TrackEventKind::Midi {
+                        channel,
+                        message: midly::MidiMessage::ProgramChange { program },
+                    } if track_info.program.is_none() => {
+                        debug!(
+                            "Program change: {} - {} channel {} track {}",
+                            program,
+                            INSTRUMENTS[program.as_int() as usize],
+                            channel,
+                            track_num
+                        );
+                        track_info.program = Some(program.as_int());
+                        track_info.is_drums = channel == PERCUSSION_CHANNEL;
+                    }
+                    kind if matches!(NoteEvent::from_kind(&kind), Some(NoteEvent::Start { .. })) => {
+                        track_info.note_count += 1;
+                    }
+                    _ => {}
+                }
+            }
+        }
+        // Names of a single track song are for the whole song
+        if !is_single_track {
+            for (track_info, names) in info.tracks.iter_mut().zip(source.track_names()) {
+                track_info.name = names.name;
+                track_info.instrument_name = names.instrument_name;
+            }
+        }
+        info
+    }
+
+    pub fn key_name(&self) -> Option<String> {
+        const MAJOR_KEYS: [&str; 15] = [
+            "Cb", "Gb", "Db", "Ab", "Eb", "Bb", "F", "C", "G", "D", "A", "E", "B", "F#", "C#",
+        ];
+        const MINOR_KEYS: [&str; 15] = [
+            "Ab", "Eb", "Bb", "F", "C", "G", "D", "A", "E", "B", "F#", "C#", "G#", "D#", "A#",
+        ];
+
+        let (sharps, minor) = self.key_signature?;
+        let index = usize::try_from(sharps as i16 + 7).ok()?;
+        if minor {
+            MINOR_KEYS.get(index).map(|key| format!("{} minor", key))
+        } else {
+            MAJOR_KEYS.get(index).map(|key| format!("{} major", key))
+        }
+    }
+}
+
+// Pitch classes from C, with sharps for the black keys
+const NOTE_NAMES: [&str; 12] = ["C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B"];
+
+/// The scientific pitch name of a MIDI note, like E2 for 40 and C4 for middle C
+pub fn note_name(midi: u8) -> String {
+    format!("{}{}", NOTE_NAMES[midi as usize % 12], midi as i32 / 12 - 1)
+}
+
+/// The part of the song an event belongs to when selecting what to play,
+/// single track songs are split up by channel since there's only one track
+pub fn selection_unit(format: Format, track_num: usize, kind: &TrackEventKind) -> usize {
+    match (format, kind) {
+        (Format::SingleTrack, TrackEventKind::Midi { channel, .. }) => channel.as_int() as usize,
+        _ => track_num,
+    }
+}
+
+/// Handles for controlling playback from another thread
+#[derive(Debug, Clone)]
+pub struct PlayerControls {
+    pub stop: Arc<AtomicBool>,
+    pub paused: Arc<AtomicBool>,
+}
+
+/// The state of playback, passed to the progress callback as the song plays
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ProgressEvent {
+    pub tick: u64,
+    pub elapsed_micros: u64,
+    // Microseconds per beat, None for timecode timing which has no tempo
+    pub tempo: Option<u64>,
+    pub playback_speed: f64,
+    pub paused: bool,
+}
+
+impl PlayerControls {
+    pub fn stop(&self) {
+        self.stop.store(true, atomic::Ordering::Relaxed);
+    }
+
+    pub fn set_paused(&self, paused: bool) {
+        self.paused.store(paused, atomic::Ordering::Relaxed);
+    }
+
+    pub fn toggle_pause(&self) {
+        self.paused.fetch_xor(true, atomic::Ordering::Relaxed);
+    }
+}
+
+pub struct WebfishingPlayer<'a> {
+    source: Rc<dyn EventSource<'a> + 'a>,
+    shift: i8,
+    // The lowest and highest notes the tuning can play
+    min_note: u8,
+    max_note: u8,
+    ticks_per_beat: Option<u64>,
+    micros_per_tick: f64,
+    // Sorted by time, playback walks through them with event_index
+    events: Vec<TimedEvent<'a>>,
+    event_index: usize,
+    current_tick: u64,
+    // Whether a streamed source can still add events, and the tick of the last one it added
+    streaming: bool,
+    stream_tick: u64,
+    // None on a dry run until something needs it, so actions can be listed without a display
+    enigo: Option<Enigo>,
+    // Sends the inputs instead of enigo on Wayland
+    #[cfg(all(feature = "wayland", target_os = "linux", not(feature = "silent_input")))]
+    virtual_input: Option<VirtualInput>,
+    window: &'a dyn WindowGeometry,
+    // One entry per string of the tuning
+    string_notes: Vec<[u8; FRET_COUNT]>,
+    cur_string_positions: HashMap<i32, i32>,
+    strings_played: Vec<bool>,
+    // Notes that haven't had a NoteOff yet, (channel, key) = string
+    active_notes: HashMap<(u8, u8), i32>,
+    last_string_usage_time: Vec<Instant>,
+    // Pitch bend of each channel in semitones
+    pitch_bends: [i8; 16],
+    // Modulation wheel of each channel
+    modulation: [u8; 16],
+    // Whether the sustain pedal of each channel is held
+    sustain: [bool; 16],
+    // Notes released while the sustain pedal was held
+    sustained_notes: Vec<(u8, u8)>,
+    // The (channel, key) the sing key is held for until its NoteOff
+    held_sing: Option<(u8, u8)>,
+    // The sing key that is actually down in the game
+    sing_key_down: Option<char>,
+    // The last note sung, to tell which way the melody moves
+    last_sung_note: Option<u8>,
+    press_hold: Duration,
+    inter_note_gap: Duration,
+    fret_to_strum_delay: Duration,
+    double_strum: bool,
+    scale_sleep_with_speed: bool,
+    // When the last input was released, used to keep the gap between inputs
+    last_release: Option<Instant>,
+    calibration: Calibration,
+    fret_input_mode: FretInputMode,
+    fret_button: FretButton,
+    humanize: Option<HumanizeSettings>,
+    // State of the random numbers for humanizing
+    humanize_rng: u64,
+    loop_midi: bool,
+    wait_for_user: bool,
+    sing_mode: SingMode,
+    hold_sing: bool,
+    contour_singing: bool,
+    sing_tracks: Option<Vec<usize>>,
+    chord_mode: bool,
+    dry_run: bool,
+    key_map: KeyMap,
+    hotkeys: Hotkeys,
+    progress_template: String,
+    voice_priority: VoicePriority,
+    range_strategy: RangeStrategy,
+    allow_octave_substitution: bool,
+    tracks: Vec<usize>,
+    track_shifts: HashMap<usize, i8>,
+    interpret_cc: bool,
+    interpret_pitch_bend: bool,
+    pitch_bend_range: f64,
+    vibrato_from_modwheel: bool,
+    count_in_beats: u8,
+    movement_weight: f64,
+    prefer_open_strings: bool,
+    optimize_fingering: bool,
+    // The planned string of each NoteOn by (tick, channel, key)
+    fingering: HashMap<(u64, u8, u8), i32>,
+    min_velocity: u8,
+    quantize: Option<u32>,
+    sustain_restrum_ms: Option<u64>,
+    min_restrum: Duration,
+    skip_percussion: bool,
+    dedup_tracks: bool,
+    max_fret: i32,
+    // Frets are counted from the capo, it's added back when a fret is clicked
+    capo: i32,
+    max_fret_jump: Option<i32>,
+    disabled_strings: Vec<bool>,
+    string_preference: Vec<i32>,
+    auto_pause_on_blur: bool,
+    on_input_error: ContinueOrAbort,
+    // Whether the current pause came from the window losing focus
+    paused_by_blur: bool,
+    report_timing: bool,
+    timing_report: TimingReport,
+    mapping_issues: Vec<MappingIssue>,
+    max_notes_per_second: Option<u32>,
+    rate_limit_strategy: RateLimitStrategy,
+    // When the notes within the rate window were started
+    recent_note_starts: VecDeque<Instant>,
+    // Notes held back by the rate limit and when they were due
+    pending_notes: VecDeque<(MidiNote, Instant)>,
+    // The A/B loop section in milliseconds and the ticks it starts and ends on
+    loop_millis: Option<(u64, u64)>,
+    loop_ticks: Option<(u64, u64)>,
+    // f64 bits so the progress bar can show the live speed
+    playback_speed: Arc<AtomicU64>,
+    speed_ramp: Option<Duration>,
+    // Song time the speed ramp started at, None once it isn't ramping
+    ramp_start_micros: Option<u64>,
+    start_time: Option<u64>,
+    start_offset_ticks: Option<u64>,
+    resume_from: Option<PlaybackState>,
+    // Times the song or loop section was played through during this playback
+    loops_played: u32,
+    // Where the last playback was stopped, None when it played to the end
+    interrupted_at: Option<PlaybackState>,
+    // None plays without a progress bar and only logs
+    multi: Option<&'a MultiProgress>,
+    on_progress: Option<Box<dyn FnMut(ProgressEvent) + 'a>>,
+    paused: Arc<AtomicBool>,
+    stop: Arc<AtomicBool>,
+    song_elapsed_micros: Arc<AtomicU64>,
+    // When the song was at clock_start_micros, events are scheduled against this
+    clock_start: Instant,
+    clock_start_micros: u64,
+    pause_pressed: bool,
+    up_pressed: bool,
+    down_pressed: bool,
+    left_pressed: bool,
+    right_pressed: bool,
+
+    // None on a dry run until something needs it, like enigo
+    #[cfg(all(feature = "silent_input", not(windows), not(target_os = "macos")))]
+    display: Option<XDisplay>,
+    // Used to ask the window manager which window is active
+    #[cfg(not(any(windows, target_os = "macos")))]
+    focus_watch: Option<(xcb::Connection, x::Atom)>,
+    // The process owning the game window, macOS events are posted to it
+    #[cfg(all(feature = "silent_input", target_os = "macos"))]
+    pid: i32,
+}
+
+/// A single input the player sends to the game
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PlayerAction {
+    SetFret { string: i32, fret: i32 },
+    Strum { string: i32 },
+    // Several strings strummed within one press/release window
+    StrumChord { strings: Vec<i32> },
+    Sing { note: u8 },
+    // Presses the sing key and keeps it down until SingRelease
+    SingHold { note: u8 },
+    SingRelease,
+    Wait(Duration),
+}
+
+enum InputCommand {
+    None,
+    Quit,
+    Seek,
+}
+
+// A NoteOn waiting to be mapped onto the guitar
+#[derive(Clone, Copy)]
+struct MidiNote {
+    // The pitch after shifting
+    note: u8,
+    track: u32,
+    channel: u8,
+    // The original key, used to match the NoteOff
+    key: u8,
+    velocity: u8,
+}
+
+struct GuitarPosition {
+    string: i32, // 0 is the lowest string of the tuning
+    fret: i32,   // 0 means open string, 1-15 for frets
+}
+
+/// What the string a note is played on depends on, so notes can be placed on the
+/// fretboard without a player. Strings are indexed from the lowest string of the tuning
+#[derive(Debug, Clone, Copy)]
+pub struct StringChoice<'s> {
+    // The open note of each string
+    pub tuning: &'s [u8],
+    pub max_fret: i32,
+    pub disabled_strings: &'s [bool],
+    // Strings to pick first in order, the rest come after by when they were last used
+    pub string_preference: &'s [i32],
+    // Strings already played on this tick, they can't play another note on it
+    pub strings_played: &'s [bool],
+    // Strings still ringing a note, the ones that aren't are used first
+    pub sounding_strings: &'s [bool],
+    // The fret each string is held on, strings that aren't in it are open
+    pub fret_positions: &'s HashMap<i32, i32>,
+    // When each string was last played, the least recently used ones are preferred
+    pub last_used: &'s [Instant],
+    pub allow_octave_substitution: bool,
+    pub prefer_open_strings: bool,
+    // How much the distance the fret has to move counts against a string
+    pub movement_weight: f64,
+}
+
+impl StringChoice<'_> {
+    /// The string and fret to play the note on, the planned string when it's free.
+    /// None when no free string can play the note
+    pub fn best_string(&self, note: u8, planned_string: Option<i32>) -> Option<(i32, i32)> {
+        let mut candidates = self.free_positions(note);
+
+        // Rather than dropping the note, try the same note an octave up or down on another string
+        if candidates.is_empty() && self.allow_octave_substitution {
+            for substitute in [note.checked_add(12), note.checked_sub(12)].into_iter().flatten() {
+                candidates = self.free_positions(substitute);
+                if !candidates.is_empty() {
+                    info!(
+                        "Substituting note {} with {}, its strings are taken",
+                        note_name(note),
+                        note_name(substitute)
+                    );
+                    break;
+                }
+            }
+        }
+
+        if candidates.is_empty() && self.only_on_disabled_strings(note) {
+            warn!("Note {} can only be played on disabled strings", note_name(note));
+        }
+
+        // Sort candidates by whether the string is still sounding a note,
+        // then by the preference order and then by last usage time (ascending order)
+        candidates.sort_by_key(|&(string, _)| {
+            let preference = self.string_preference.iter().position(|&preferred| preferred == string);
+            (
+                self.is_sounding(string),
+                preference.unwrap_or(usize::MAX),
+                self.last_used.get(string as usize),
+            )
+        });
+
+        // Follow the planned fingering when its string is free
+        let planned = planned_string.and_then(|string| candidates.iter().find(|c| c.0 == string).copied());
+
+        // Otherwise select the best candidate, the free one with the lowest cost of
+        // its usage rank plus the weighted distance the fret has to move
+        planned.or_else(|| {
+            candidates
+                .iter()
+                .enumerate()
+                .min_by(|&(a_rank, a), &(b_rank, b)| {
+                    let a_cost = (self.is_sounding(a.0), self.string_cost(a_rank, a));
+                    let b_cost = (self.is_sounding(b.0), self.string_cost(b_rank, b));
+                    a_cost.partial_cmp(&b_cost).unwrap_or(Ordering::Equal)
+                })
+                .map(|(_, &candidate)| candidate)
+        })
+    }
+
+    // Every string not played yet this tick that can play the note, with the fret to play it on
+    fn free_positions(&self, note: u8) -> Vec<(i32, i32)> {
+        let mut candidates: Vec<(i32, i32)> = Vec::new();
+
+        for (string_index, &open_note) in self.tuning.iter().enumerate() {
+            if self.strings_played.get(string_index).copied().unwrap_or(false) {
+                continue; // Skip if this string has already been played
+            }
+            if self.disabled_strings.get(string_index).copied().unwrap_or(false) {
+                continue;
+            }
+
+            if let Some(fret) = self.fret_for(open_note, note) {
+                // Found a match, add to candidates
+                candidates.push((string_index as i32, fret));
+            }
+        }
+
+        candidates
+    }
+
+    // The fret that plays the note on a string, None when it's below the string or too high
+    fn fret_for(&self, open_note: u8, note: u8) -> Option<i32> {
+        let fret = note.checked_sub(open_note)? as i32;
+        (fret <= self.max_fret.min(MAX_FRET)).then_some(fret)
+    }
+
+    // Whether every string that can play the note is disabled
+    fn only_on_disabled_strings(&self, note: u8) -> bool {
+        let mut strings = self
+            .tuning
+            .iter()
+            .enumerate()
+            .filter(|(_, &open_note)| self.fret_for(open_note, note).is_some())
+            .map(|(string, _)| string)
+            .peekable();
+        strings.peek().is_some()
+            && strings.all(|string| self.disabled_strings.get(string).copied().unwrap_or(false))
+    }
+
+    fn string_cost(&self, usage_rank: usize, &(string, fret): &(i32, i32)) -> f64 {
+        let current_fret = self.fret_positions.get(&string).copied().unwrap_or(0);
+        let open_bonus = if self.prefer_open_strings && fret == 0 {
+            OPEN_STRING_BIAS
+        } else {
+            0.0
+        };
+        usage_rank as f64 + self.movement_weight * (fret - current_fret).abs() as f64 - open_bonus
+    }
+
+    fn is_sounding(&self, string: i32) -> bool {
+        self.sounding_strings.get(string as usize).copied().unwrap_or(false)
+    }
+}
+
+impl<'a> WebfishingPlayer<'a> {
+    pub fn new(
+        settings: PlayerSettings<'a>,
+        wait_for_user: bool,
+        input_sleep_duration: u64,
+        window: &'a dyn WindowGeometry,
+        multi: Option<&'a MultiProgress>,
+    ) -> Result<Self, PlayerError> {
+        let source = settings.source;
+        match source.header().format {
+            Format::SingleTrack => info!("Single track file, its channels are chosen like tracks"),
+            Format::Sequential => info!("Sequential file, the tracks play one after another"),
+            Format::Parallel => {}
+        }
+
+        let mut tracks = settings.tracks.unwrap_or_default();
+        if let Some(track_names) = &settings.track_names {
+            for track in WebfishingPlayer::resolve_track_names(source.as_ref(), track_names)? {
+                if !tracks.contains(&track) {
+                    tracks.push(track);
+                }
+            }
+        }
+        if tracks.is_empty() && settings.auto_select_tracks {
+            tracks = WebfishingPlayer::auto_select_tracks(source.as_ref(), settings.skip_percussion);
+            info!("Automatically chose tracks {:?}", tracks);
+        }
+        let has_notes = WebfishingPlayer::get_unit_notes(source.as_ref(), settings.skip_percussion)
+            .iter()
+            .any(|(unit, _)| tracks.contains(unit));
+        // A stream's notes only come once it plays
+        if !has_notes && !source.is_stream() {
+            return Err(PlayerError::NoPlayableNotes);
+        }
+
+        #[cfg(all(feature = "silent_input", not(windows), not(target_os = "macos")))]
+        let display = if settings.dry_run { None } else { Some(XDisplay::open()?) };
+
+        #[cfg(all(feature = "silent_input", target_os = "macos"))]
+        let pid = window_owner_pid(window.id()).ok_or(PlayerError::FindProcess)?;
+
+        #[cfg(not(any(windows, target_os = "macos")))]
+        let focus_watch = if settings.auto_pause_on_blur {
+            let focus_watch = connect_focus_watch();
+            if focus_watch.is_none() {
+                warn!("The window manager doesn't report the active window, playback won't pause on focus loss");
+            }
+            focus_watch
+        } else {
+            None
+        };
+
+        let max_fret = settings.max_fret.clamp(0, MAX_FRET);
+        WebfishingPlayer::validate_tuning(&settings.tuning, max_fret)?;
+        let capo = settings.capo.clamp(0, max_fret);
+        let (tuning, max_fret) = WebfishingPlayer::with_capo(&settings.tuning, max_fret, capo);
+        if settings.key_map.strings.len() < settings.tuning.len() {
+            return Err(PlayerError::InvalidKeyMap(format!(
+                "{} strings but only {} strum keys",
+                settings.tuning.len(),
+                settings.key_map.strings.len()
+            )));
+        }
+        // Inputs aren't sent on a dry run, the window can be anything
+        if !settings.dry_run {
+            WebfishingPlayer::validate_window(window, &settings.calibration, settings.tuning.len() as i32)?;
+        }
+
+        let ticks_per_beat = match source.header().timing {
+            midly::Timing::Metrical(ppq) => Some(ppq.as_int() as u64),
+            // A tick would last forever and playback would never move on
+            midly::Timing::Timecode(_, 0) => {
+                return Err(PlayerError::InvalidTiming("timecode with 0 ticks per frame".to_string()));
+            }
+            midly::Timing::Timecode(fps, subframe) => {
+                info!(
+                    "Timecode timing: {} fps, {} ticks per frame - {:.3}µs per tick",
+                    fps.as_f32(),
+                    subframe,
+                    WebfishingPlayer::timecode_micros_per_tick(fps, subframe)
+                );
+                None
+            }
+        };
+
+        let (min_note, max_note) = WebfishingPlayer::note_range(&tuning, max_fret);
+        let notes = WebfishingPlayer::get_unit_notes(source.as_ref(), settings.skip_percussion);
+        let optimal_shift =
+            WebfishingPlayer::calculate_optimal_shift(&notes, min_note, max_note, settings.shift_weight);
+        let shift = settings.manual_shift.unwrap_or(optimal_shift);
+        info!("Using shift: {} (suggested {})", shift, optimal_shift);
+        let mut player = WebfishingPlayer {
+            source,
+            shift,
+            min_note,
+            max_note,
+            ticks_per_beat,
+            micros_per_tick: 0.0,
+            events: Vec::new(),
+            event_index: 0,
+            current_tick: 0,
+            streaming: false,
+            stream_tick: 0,
+            enigo: if settings.dry_run { None } else { Some(Enigo::new(&Settings::default())?) },
+            #[cfg(all(feature = "wayland", target_os = "linux", not(feature = "silent_input")))]
+            virtual_input: if settings.dry_run { None } else { open_virtual_input() },
+            window,
+            string_notes: WebfishingPlayer::build_string_notes(&tuning),
+            cur_string_positions: HashMap::new(),
+            strings_played: vec![false; settings.tuning.len()],
+            active_notes: HashMap::new(),
+            last_string_usage_time: vec![Instant::now(); settings.tuning.len()],
+            pitch_bends: [0; 16],
+            modulation: [0; 16],
+            sustain: [false; 16],
+            sustained_notes: Vec::new(),
+            held_sing: None,
+            sing_key_down: None,
+            last_sung_note: None,
+            press_hold: Duration::from_millis(settings.press_hold_ms.unwrap_or(input_sleep_duration)),
+            inter_note_gap: Duration::from_millis(
+                settings.inter_note_gap_ms.unwrap_or(input_sleep_duration),
+            ),
+            fret_to_strum_delay: Duration::from_millis(settings.fret_to_strum_delay_ms),
+            double_strum: settings.double_strum,
+            scale_sleep_with_speed: settings.scale_sleep_with_speed,
+            last_release: None,
+            calibration: settings.calibration,
+            fret_input_mode: settings.fret_input_mode,
+            fret_button: settings.fret_button,
+            humanize: settings.humanize,
+            // Any odd seed works, the time keeps performances from being identical
+            humanize_rng: SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .map_or(1, |time| time.as_nanos() as u64 | 1),
+            loop_midi: settings.loop_midi,
+            wait_for_user,
+            sing_mode: settings.sing_mode,
+            hold_sing: settings.hold_sing,
+            contour_singing: settings.contour_singing,
+            sing_tracks: settings.sing_tracks,
+            chord_mode: settings.chord_mode,
+            dry_run: settings.dry_run,
+            key_map: settings.key_map,
+            hotkeys: settings.hotkeys,
+            progress_template: settings
+                .progress_template
+                .unwrap_or_else(|| PROGRESS_TEMPLATE.to_string()),
+            voice_priority: settings.voice_priority,
+            range_strategy: settings.range_strategy,
+            allow_octave_substitution: settings.allow_octave_substitution,
+            tracks,
+            track_shifts: settings.track_shifts,
+            interpret_cc: settings.interpret_cc,
+            interpret_pitch_bend: settings.interpret_pitch_bend,
+            pitch_bend_range: settings.pitch_bend_range,
+            vibrato_from_modwheel: settings.vibrato_from_modwheel,
+            count_in_beats: settings.count_in_beats,
+            movement_weight: settings.movement_weight,
+            prefer_open_strings: settings.prefer_open_strings,
+            optimize_fingering: settings.optimize_fingering,
+            fingering: HashMap::new(),
+            min_velocity: settings.min_velocity,
+            quantize: settings.quantize.filter(|&grid| grid > 0),
+            sustain_restrum_ms: settings.sustain_restrum_ms.filter(|&interval| interval > 0),
+            min_restrum: Duration::from_millis(settings.min_restrum_ms.unwrap_or(0)),
+            skip_percussion: settings.skip_percussion,
+            dedup_tracks: settings.dedup_tracks,
+            max_fret,
+            capo,
+            max_fret_jump: settings.max_fret_jump.filter(|&jump| jump > 0),
+            disabled_strings: (0..settings.tuning.len())
+                .map(|string| settings.disabled_strings.get(string).copied().unwrap_or(false))
+                .collect(),
+            string_preference: settings.string_preference,
+            auto_pause_on_blur: settings.auto_pause_on_blur,
+            on_input_error: settings.on_input_error,
+            paused_by_blur: false,
+            report_timing: settings.report_timing,
+            timing_report: TimingReport::default(),
+            mapping_issues: Vec::new(),
+            max_notes_per_second: settings.max_notes_per_second.filter(|&max| max > 0),
+            rate_limit_strategy: settings.rate_limit_strategy,
+            recent_note_starts: VecDeque::new(),
+            pending_notes: VecDeque::new(),
+            loop_millis: None,
+            loop_ticks: None,
+            playback_speed: Arc::new(AtomicU64::new(settings.playback_speed.to_bits())),
+            speed_ramp: settings.speed_ramp,
+            ramp_start_micros: None,
+            start_time: settings.start_time,
+            start_offset_ticks: settings.start_offset_ticks,
+            resume_from: settings.resume_from,
+            loops_played: 0,
+            interrupted_at: None,
+            multi,
+            on_progress: None,
+            paused: Arc::new(AtomicBool::new(false)),
+            stop: Arc::new(AtomicBool::new(false)),
+            song_elapsed_micros: Arc::new(AtomicU64::new(0)),
+            clock_start: Instant::now(),
+            clock_start_micros: 0,
+            pause_pressed: false,
+            up_pressed: false,
+            down_pressed: false,
+            left_pressed: false,
+            right_pressed: false,
+
+            #[cfg(all(feature = "silent_input", not(windows), not(target_os = "macos")))]
+            display,
+            #[cfg(not(any(windows, target_os = "macos")))]
+            focus_watch,
+            #[cfg(all(feature = "silent_input", target_os = "macos"))]
+            pid,
+        };
+
+        player.reset();
+
+        if let Some(bar) = settings.start_bar {
+            match player.bar_start_tick(bar) {
+                Some(tick) => player.start_offset_ticks = Some(tick),
+                None => warn!("Can't find bars in a song with timecode timing, starting from the beginning"),
+            }
+        }
+
+        match (settings.loop_start, settings.loop_end) {
+            (Some(loop_start), Some(loop_end)) if loop_start < loop_end => {
+                player.loop_millis = Some((loop_start, loop_end));
+                player.loop_ticks = Some((
+                    player.tick_at_millis(loop_start),
+                    player.tick_at_millis(loop_end),
+                ));
+            }
+            (Some(_), Some(_)) => warn!("Loop start has to be before the loop end, ignoring the loop"),
+            _ => {}
+        }
+
+        Ok(player)
+    }
+
+    fn prepare_events(&mut self) {
+        self.events.clear();
+        let source = Rc::clone(&self.source);
+        let format = source.header().format;
+        // Tempo comes from the first track that has any so conflicting tempo maps
+        // across tracks can't change the speed, sequential tracks each keep their own
+        let tempo_track = source.tracks().iter().position(|track| {
+            track
+                .iter()
+                .any(|event| matches!(event.kind, TrackEventKind::Meta(midly::MetaMessage::Tempo(_))))
+        });
+        let mut track_start = 0;
+        for (track_num, track) in source.tracks().iter().enumerate() {
+            // Sequential tracks play one after another instead of all at once
+            let mut absolute_time = if format == Format::Sequential {
+                track_start
+            } else {
+                0
+            };
+            let mut note_starts = HashMap::new();
+            for event in track {
+                absolute_time += event.delta.as_int() as u64;
+                let unit = selection_unit(format, track_num, &event.kind);
+                // Skip non-meta events
+                if !self.tracks.contains(&unit) && !matches!(event.kind, TrackEventKind::Meta(_)) {
+                    continue;
+                }
+                if matches!(event.kind, TrackEventKind::Meta(midly::MetaMessage::Tempo(_)))
+                    && format != Format::Sequential
+                    && tempo_track != Some(track_num)
+                {
+                    continue;
+                }
+                self.events.push(TimedEvent {
+                    absolute_time: self.quantized_time(absolute_time, &event.kind, &mut note_starts),
+                    event: *event,
+                    track: unit as u32,
+                    restrum: false,
+                });
+            }
+            track_start = absolute_time;
+        }
+        // Stable so events that still tie keep their order in the file
+        self.events.sort();
+        self.merge_duplicate_tracks();
+        self.dedupe_tempos();
+        self.add_restrums();
+        self.event_index = 0;
+        self.streaming = source.is_stream();
+        self.stream_tick = 0;
+        if self.optimize_fingering {
+            self.plan_fingering();
+        }
+    }
+
+    // Adds the events a streamed source sent since the last poll. They come in order
+    // so they go on the end, and ones that are already late play on the current tick
+    fn poll_stream(&mut self) {
+        if !self.streaming {
+            return;
+        }
+        let source = Rc::clone(&self.source);
+        let Some(events) = source.poll_events() else {
+            self.streaming = false;
+            return;
+        };
+
+        for event in events {
+            self.stream_tick += event.delta.as_int() as u64;
+            // Streams are a single track, the chosen tracks stand for channels
+            let unit = match event.kind {
+                TrackEventKind::Midi { channel, .. } => channel.as_int() as usize,
+                _ => 0,
+            };
+            let chosen = self.tracks.is_empty() || self.tracks.contains(&unit);
+            if !chosen && !matches!(event.kind, TrackEventKind::Meta(_)) {
+                continue;
+            }
+            self.events.push(TimedEvent {
+                absolute_time: self.stream_tick.max(self.current_tick),
+                event,
+                track: unit as u32,
+                restrum: false,
+            });
+        }
+    }
+
+    // Finds chosen tracks that double another one, which would strum every note twice and
+    // use up the strings, and leaves them out when deduplicating or warns about them otherwise
+    fn merge_duplicate_tracks(&mut self) {
+        let mut track_notes: HashMap<u32, HashSet<(u64, u8)>> = HashMap::new();
+        for timed_event in &self.events {
+            if let Some(NoteEvent::Start { key, .. }) = timed_event.note_event() {
+                track_notes
+                    .entry(timed_event.track)
+                    .or_default()
+                    .insert((timed_event.absolute_time, key));
+            }
+        }
+
+        let mut tracks: Vec<u32> = track_notes.keys().copied().collect();
+        tracks.sort();
+        let mut duplicates = HashSet::new();
+        for (index, &track) in tracks.iter().enumerate() {
+            for &other in &tracks[index + 1..] {
+                if duplicates.contains(&track) || duplicates.contains(&other) {
+                    continue;
+                }
+                let (notes, other_notes) = (&track_notes[&track], &track_notes[&other]);
+                let shared = notes.intersection(other_notes).count();
+                let similarity = shared as f64 / notes.len().max(other_notes.len()) as f64;
+                if similarity < DUPLICATE_TRACK_SIMILARITY {
+                    continue;
+                }
+
+                if self.dedup_tracks {
+                    info!("Track {} duplicates track {}, leaving it out", other, track);
+                    duplicates.insert(other);
+                } else {
+                    warn!("Track {} duplicates track {}, every note will be played twice", other, track);
+                }
+            }
+        }
+
+        if !duplicates.is_empty() {
+            self.events.retain(|timed_event| {
+                !duplicates.contains(&timed_event.track)
+                    || !matches!(timed_event.event.kind, TrackEventKind::Midi { .. })
+            });
+        }
+    }
+
+    // Keeps only the last tempo event of each tick and drops the ones that don't change the tempo
+    fn dedupe_tempos(&mut self) {
+        let mut keep = vec![true; self.events.len()];
+        let mut last_tempo: Option<(usize, u64, u24)> = None;
+        for (index, timed_event) in self.events.iter().enumerate() {
+            let TrackEventKind::Meta(midly::MetaMessage::Tempo(tempo)) = timed_event.event.kind else {
+                continue;
+            };
+            if let Some((last_index, last_tick, last)) = last_tempo {
+                if last_tick == timed_event.absolute_time {
+                    // A later tempo on the same tick replaces the earlier one
+                    keep[last_index] = false;
+                } else if last == tempo {
+                    keep[index] = false;
+                    continue;
+                }
+            }
+            last_tempo = Some((index, timed_event.absolute_time, tempo));
+        }
+        let before = self.events.len();
+        let mut keep = keep.into_iter();
+        self.events.retain(|_| keep.next().unwrap_or(true));
+        let dropped = before - self.events.len();
+        if dropped > 0 {
+            debug!("Dropped {} redundant tempo events", dropped);
+        }
+    }
+
+    // Picks the string of every note ahead of time so the whole song needs as few fret
+    // changes as possible, find_best_string follows the plan while the planned string is free
+    fn plan_fingering(&mut self) {
+        self.fingering.clear();
+        let string_count = self.string_notes.len();
+
+        // The notes in the order they're given strings, per tick by priority
+        let mut notes = Vec::new();
+        let mut index = 0;
+        while index < self.events.len() {
+            let tick = self.events[index].absolute_time;
+            let mut group = Vec::new();
+            while index < self.events.len() && self.events[index].absolute_time == tick {
+                let timed_event = self.events[index];
+                index += 1;
+                if timed_event.restrum {
+                    continue;
+                }
+                if let Some(NoteEvent::Start { channel, key, vel }) = timed_event.note_event() {
+                    if vel < self.min_velocity || (self.skip_percussion && channel == PERCUSSION_CHANNEL) {
+                        continue;
+                    }
+                    group.push(MidiNote {
+                        note: self.shifted_note(key, timed_event.track as usize),
+                        track: timed_event.track,
+                        channel,
+                        key,
+                        velocity: vel,
+                    });
+                }
+            }
+            self.sort_by_priority(&mut group);
+            for midi_note in group {
+                if let Some(note) = self.fit_to_range(midi_note.note) {
+                    notes.push((tick, midi_note, note));
+                }
+            }
+        }
+
+        // Shortest path over the positions of each note. Every state is a position for the
+        // latest note with the frets it leaves the strings on and the strings taken on its tick
+        let mut states: Vec<(Vec<i32>, Vec<bool>, u32)> =
+            vec![(vec![0; string_count], vec![false; string_count], 0)];
+        // The position of each state and the state before it, per planned note
+        let mut steps: Vec<Vec<((i32, i32), usize)>> = Vec::new();
+        let mut planned_notes = Vec::new();
+        let mut last_tick = None;
+        for (tick, midi_note, note) in notes {
+            if last_tick != Some(tick) {
+                for (_, used, _) in &mut states {
+                    used.fill(false);
+                }
+                last_tick = Some(tick);
+            }
+
+            let mut next_states = Vec::new();
+            let mut step = Vec::new();
+            for (string, notes) in self.string_notes.iter().enumerate() {
+                if self.disabled_strings[string] {
+                    continue;
+                }
+                let reachable = notes.get(..=self.max_fret as usize).unwrap_or(notes);
+                let Some(fret) = reachable.iter().position(|&n| n == note) else {
+                    continue;
+                };
+                let fret = fret as i32;
+
+                // The cheapest state to come from that leaves the string free on this tick
+                let best = states
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, (_, used, _))| !used[string])
+                    .map(|(i, (frets, _, cost))| (cost + u32::from(frets[string] != fret), i))
+                    .min();
+                if let Some((cost, previous)) = best {
+                    let (mut frets, mut used, _) = states[previous].clone();
+                    frets[string] = fret;
+                    used[string] = true;
+                    next_states.push((frets, used, cost));
+                    step.push(((string as i32, fret), previous));
+                }
+            }
+
+            // No string is left for the note, it gets dropped when playing too
+            if next_states.is_empty() {
+                continue;
+            }
+            states = next_states;
+            steps.push(step);
+            planned_notes.push((tick, midi_note.channel, midi_note.key));
+        }
+
+        // Follow the cheapest plan back from the last note
+        let Some((cost, mut state)) = states.iter().enumerate().map(|(i, (_, _, cost))| (*cost, i)).min() else {
+            return;
+        };
+        info!("Planned fingering with {} fret changes", cost);
+        for (step, planned_note) in steps.iter().zip(planned_notes).rev() {
+            let ((string, _), previous) = step[state];
+            self.fingering.insert(planned_note, string);
+            state = previous;
+        }
+    }
+
+    // Adds re-strums to notes held longer than the re-strum interval so they don't fade out
+    fn add_restrums(&mut self) {
+        let Some(interval) = self.sustain_restrum_ms else {
+            return;
+        };
+
+        let mut note_starts: HashMap<(u8, u8), TimedEvent<'a>> = HashMap::new();
+        let mut restrums = Vec::new();
+        for timed_event in &self.events {
+            match timed_event.note_event() {
+                Some(NoteEvent::Start { channel, key, .. }) => {
+                    note_starts.insert((channel, key), *timed_event);
+                }
+                Some(NoteEvent::End { channel, key }) => {
+                    let Some(start) = note_starts.remove(&(channel, key)) else {
+                        continue;
+                    };
+                    let end_millis = self.millis_at_tick(timed_event.absolute_time);
+                    let mut restrum_millis = self.millis_at_tick(start.absolute_time) + interval;
+                    while restrum_millis < end_millis {
+                        restrums.push(TimedEvent {
+                            absolute_time: self.tick_at_millis(restrum_millis),
+                            restrum: true,
+                            ..start
+                        });
+                        restrum_millis += interval;
+                    }
+                }
+                None => {}
+            }
+        }
+
+        self.events.extend(restrums);
+        self.events.sort();
+    }
+
+    // Snaps channel events to the quantize grid, meta events like tempo changes keep their exact tick.
+    // A note ends at least a step after it starts, snapped onto its start it would be released
+    // before it's played and hold its string forever. note_starts has the snapped starts of the track
+    fn quantized_time(
+        &self,
+        absolute_time: u64,
+        kind: &TrackEventKind,
+        note_starts: &mut HashMap<(u8, u8), u64>,
+    ) -> u64 {
+        let (Some(grid), TrackEventKind::Midi { .. }) = (self.quantize, kind) else {
+            return absolute_time;
+        };
+        let grid = grid as u64;
+        let time = (absolute_time + grid / 2) / grid * grid;
+        match NoteEvent::from_kind(kind) {
+            Some(NoteEvent::Start { channel, key, .. }) => {
+                note_starts.insert((channel, key), time);
+                time
+            }
+            Some(NoteEvent::End { channel, key }) => match note_starts.remove(&(channel, key)) {
+                Some(start) => time.max(start + grid),
+                None => time,
+            },
+            None => time,
+        }
+    }
+
+    // Moves playback back to the start of the song
+    fn rewind(&mut self) {
+        self.event_index = 0;
+        self.current_tick = 0;
+        self.micros_per_tick = self.initial_micros_per_tick();
+        self.strings_played.fill(false);
+        self.clear_sounding_notes();
+        self.song_elapsed_micros.store(0, atomic::Ordering::Relaxed);
+        self.reset_clock();
+    }
+
+    // Anchors the playback clock at the current position
+    fn reset_clock(&mut self) {
+        self.clock_start = Instant::now();
+        self.clock_start_micros = self.song_elapsed_micros.load(atomic::Ordering::Relaxed);
+    }
+
+    // The position in the song the playback clock is at
+    fn clock_micros(&self) -> u64 {
+        self.clock_start_micros + self.clock_start.elapsed().as_micros() as u64
+    }
+
+    fn clear_sounding_notes(&mut self) {
+        self.active_notes.clear();
+        self.pitch_bends = [0; 16];
+        self.modulation = [0; 16];
+        self.sustain = [false; 16];
+        self.sustained_notes.clear();
+        self.held_sing = None;
+        self.last_sung_note = None;
+        self.pending_notes.clear();
+        if let Err(e) = self.release_sing_key() {
+            warn!("Failed to release the sing key: {}", e);
+        }
+    }
+
+    // Takes the events on the next tick
+    fn next_tick_group(&mut self) -> Vec<TimedEvent<'a>> {
+        let start = self.event_index;
+        let absolute_time = self.events[start].absolute_time;
+        let end = self.events[start..]
+            .iter()
+            .position(|timed_event| timed_event.absolute_time != absolute_time)
+            .map_or(self.events.len(), |offset| start + offset);
+
+        self.event_index = end;
+        self.events[start..end].to_vec()
+    }
+
+    // Microseconds to hold back the tick by when humanizing. Inputs can't be sent before
+    // their tick, so the jitter is centred one standard deviation late instead of on time
+    fn humanize_offset(&mut self, group: &[TimedEvent]) -> u64 {
+        let Some(humanize) = self.humanize else {
+            return 0;
+        };
+
+        let mut jitter_micros = humanize.jitter_ms * 1000.0;
+        if humanize.velocity_based {
+            let loudest = group
+                .iter()
+                .filter_map(|timed_event| match timed_event.event.kind {
+                    TrackEventKind::Midi {
+                        message: midly::MidiMessage::NoteOn { vel, .. },
+                        ..
+                    } => Some(vel.as_int()),
+                    _ => None,
+                })
+                .max()
+                .unwrap_or(127);
+            jitter_micros *= 1.5 - loudest as f64 / 127.0;
+        }
+        let offset = (jitter_micros * (1.0 + self.next_gaussian())).clamp(0.0, 2.0 * jitter_micros);
+
+        // Offbeat eighths move up to a third of an eighth later for swing
+        let mut swing = 0.0;
+        if let (Some(ticks_per_beat), Some(timed_event)) = (self.ticks_per_beat, group.first()) {
+            let eighth = ticks_per_beat / 2;
+            if eighth > 0 && timed_event.absolute_time % ticks_per_beat == eighth {
+                swing = humanize.swing * eighth as f64 * self.micros_per_tick / 3.0;
+            }
+        }
+
+        ((offset + swing) / self.playback_speed()) as u64
+    }
+
+    // A standard normal random number, from xorshift64* and the Box-Muller transform
+    fn next_gaussian(&mut self) -> f64 {
+        let mut next_uniform = || {
+            self.humanize_rng ^= self.humanize_rng >> 12;
+            self.humanize_rng ^= self.humanize_rng << 25;
+            self.humanize_rng ^= self.humanize_rng >> 27;
+            let bits = self.humanize_rng.wrapping_mul(0x2545_F491_4F6C_DD1D) >> 11;
+            // In (0, 1] so the logarithm stays finite
+            (bits + 1) as f64 / (1u64 << 53) as f64
+        };
+        let (u1, u2) = (next_uniform(), next_uniform());
+        (-2.0 * u1.ln()).sqrt() * (2.0 * std::f64::consts::PI * u2).cos()
+    }
+
+    /// Checks that the window is big enough and that every fret, and the button that opens
+    /// all strings past the highest one, is inside it so clicks can't land on other windows
+    pub fn validate_window(
+        window: &dyn WindowGeometry,
+        calibration: &Calibration,
+        string_count: i32,
+    ) -> Result<(), PlayerError> {
+        if window.width() < MIN_WINDOW_WIDTH || window.height() < MIN_WINDOW_HEIGHT {
+            return Err(PlayerError::InvalidWindow(format!(
+                "{}x{} is smaller than {}x{}, is the game minimized?",
+                window.width(),
+                window.height(),
+                MIN_WINDOW_WIDTH,
+                MIN_WINDOW_HEIGHT
+            )));
+        }
+
+        let x_range = window.x()..window.x() + window.width() as i32;
+        let y_range = window.y()..window.y() + window.height() as i32;
+        for (string, fret) in [(0, 0), (0, MAX_FRET), (string_count, 0), (string_count, MAX_FRET)] {
+            let (x, y) = calibration.fret_position(window, string, fret);
+            if !x_range.contains(&x) || !y_range.contains(&y) {
+                return Err(PlayerError::InvalidWindow(format!(
+                    "string {} fret {} is at {},{} outside the window, check the calibration",
+                    string + 1,
+                    fret,
+                    x,
+                    y
+                )));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Finds the tracks with the given names, ignoring case and surrounding spaces
+    pub fn resolve_track_names(source: &dyn EventSource, names: &[String]) -> Result<Vec<usize>, PlayerError> {
+        let info = SongInfo::from_source(source);
+        let track_names: Vec<Option<&str>> = info.tracks.iter().map(|track| track.name.as_deref()).collect();
+
+        names
+            .iter()
+            .map(|name| {
+                track_names
+                    .iter()
+                    .position(|track_name| {
+                        track_name.is_some_and(|track_name| track_name.trim().eq_ignore_ascii_case(name.trim()))
+                    })
+                    .ok_or_else(|| {
+                        let available = track_names.iter().flatten().map(|name| name.to_string()).collect();
+                        PlayerError::TrackNotFound(name.clone(), available)
+                    })
+            })
+            .collect()
+    }
+
+    /// Checks that every string of the tuning can reach at least part of the playable range
+    /// using frets up to max_fret
+    pub fn validate_tuning(tuning: &[u8], max_fret: i32) -> Result<(), PlayerError> {
+        if tuning.is_empty() {
+            return Err(PlayerError::InvalidTuning("The tuning has no strings".to_string()));
+        }
+
+        for (string, &open_note) in tuning.iter().enumerate() {
+            let highest_note = open_note as usize + max_fret as usize;
+            if highest_note > 127 {
+                return Err(PlayerError::InvalidTuning(format!(
+                    "String {} tuned to {} goes past the highest MIDI note",
+                    string + 1,
+                    open_note
+                )));
+            }
+        }
+
+        // Every note between the lowest and highest has to be playable for clamping to work
+        let mut open_notes = tuning.to_vec();
+        open_notes.sort_unstable();
+        for pair in open_notes.windows(2) {
+            let highest_note = pair[0] as usize + max_fret as usize;
+            if pair[1] as usize > highest_note + 1 {
+                return Err(PlayerError::InvalidTuning(format!(
+                    "No string can play the notes between {} and {}",
+                    highest_note,
+                    pair[1]
+                )));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Finds the game window by the first name that's part of a window's app name or title,
+    /// ignoring case
+    pub fn find_window(names: &[&str]) -> Result<Window, PlayerError> {
+        let windows = Window::all().map_err(PlayerError::ListWindows)?;
+        for name in names {
+            let name = name.to_lowercase();
+            let found = windows.iter().position(|window| {
+                window.app_name().to_lowercase().contains(&name)
+                    || window.title().to_lowercase().contains(&name)
+            });
+            if let Some(index) = found {
+                return Ok(windows.into_iter().nth(index).unwrap());
+            }
+        }
+
+        Err(PlayerError::WindowNotFound(
+            windows
+                .iter()
+                .map(|window| window.title().to_string())
+                .filter(|title| !title.is_empty())
+                .collect(),
+        ))
+    }
+
+    /// Every open window with its geometry and whether it's focused and visible,
+    /// for a frontend to pick the game window from
+    pub fn list_windows() -> Result<Vec<WindowInfo>, PlayerError> {
+        let windows = Window::all().map_err(PlayerError::ListWindows)?;
+        let focused_id = focused_window_id();
+        Ok(windows
+            .iter()
+            .map(|window| WindowInfo::new(window, focused_id))
+            .collect())
+    }
+
+    /// The tuning and highest fret with a capo, each string sounds the capo fret when open.
+    /// The capo is expected to be between 0 and max_fret
+    pub fn with_capo(tuning: &[u8], max_fret: i32, capo: i32) -> (Vec<u8>, i32) {
+        let tuning = tuning.iter().map(|&open_note| open_note + capo as u8).collect();
+        (tuning, max_fret - capo)
+    }
+
+    /// The lowest and highest notes a tuning can play using frets up to max_fret
+    pub fn note_range(tuning: &[u8], max_fret: i32) -> (u8, u8) {
+        let lowest = tuning.iter().min().copied().unwrap_or_default();
+        let highest = tuning.iter().max().copied().unwrap_or_default();
+        (lowest, highest + max_fret as u8)
+    }
+
+    fn string_count(&self) -> i32 {
+        self.string_notes.len() as i32
+    }
+
+    // string_notes[string][fret] = note
+    fn build_string_notes(tuning: &[u8]) -> Vec<[u8; FRET_COUNT]> {
+        let mut string_notes = vec![[0; FRET_COUNT]; tuning.len()];
+        for (notes, &open_note) in string_notes.iter_mut().zip(tuning) {
+            for (fret, note) in notes.iter_mut().enumerate() {
+                *note = open_note + fret as u8;
+            }
+        }
+        string_notes
+    }
+
+    fn find_best_string(&mut self, note: u8, planned_string: Option<i32>) -> Option<GuitarPosition> {
+        let tuning: Vec<u8> = self.string_notes.iter().map(|notes| notes[0]).collect();
+        let sounding_strings: Vec<bool> = (0..self.string_count())
+            .map(|string| self.active_notes.values().any(|&active| active == string))
+            .collect();
+        let choice = StringChoice {
+            tuning: &tuning,
+            max_fret: self.max_fret,
+            disabled_strings: &self.disabled_strings,
+            string_preference: &self.string_preference,
+            strings_played: &self.strings_played,
+            sounding_strings: &sounding_strings,
+            fret_positions: &self.cur_string_positions,
+            last_used: &self.last_string_usage_time,
+            allow_octave_substitution: self.allow_octave_substitution,
+            prefer_open_strings: self.prefer_open_strings,
+            movement_weight: self.movement_weight,
+        };
+
+        let (string, fret) = choice.best_string(note, planned_string)?;
+        // Update last usage time for the selected string
+        self.last_string_usage_time[string as usize] = Instant::now();
+        Some(GuitarPosition { string, fret })
+    }
+
+    fn is_paused(&self) -> bool {
+        self.paused.load(atomic::Ordering::Relaxed)
+    }
+
+    fn toggle_pause(&self) {
+        self.paused.fetch_xor(true, atomic::Ordering::Relaxed);
+    }
+
+    /// Calls the callback with the playback state whenever the progress bar would update
+    pub fn on_progress(&mut self, callback: impl FnMut(ProgressEvent) + 'a) {
+        self.on_progress = Some(Box::new(callback));
+    }
+
+    fn update_progress(&mut self, pb: &ProgressBar) {
+        pb.set_position(self.current_tick);
+        if self.on_progress.is_none() {
+            return;
+        }
+
+        let event = ProgressEvent {
+            tick: self.current_tick,
+            elapsed_micros: self.song_elapsed_micros.load(atomic::Ordering::Relaxed),
+            tempo: self
+                .ticks_per_beat
+                .map(|ticks_per_beat| (self.micros_per_tick * ticks_per_beat as f64).round() as u64),
+            playback_speed: self.playback_speed(),
+            paused: self.is_paused(),
+        };
+        if let Some(on_progress) = self.on_progress.as_mut() {
+            on_progress(event);
+        }
+    }
+
+    pub fn control_handle(&self) -> PlayerControls {
+        PlayerControls {
+            stop: Arc::clone(&self.stop),
+            paused: Arc::clone(&self.paused),
+        }
+    }
+
+    pub fn stop(&self) {
+        self.stop.store(true, atomic::Ordering::Relaxed);
+    }
+
+    // Takes the stop request so the player can be played again
+    fn should_stop(&self) -> bool {
+        self.stop.swap(false, atomic::Ordering::Relaxed)
+    }
+
+    fn check_inputs(&mut self, device_state: &DeviceState) -> InputCommand {
+        if self.should_stop() {
+            return InputCommand::Quit;
+        }
+
+        let keys = device_state.get_keys();
+        if keys.contains(&self.hotkeys.quit) {
+            return InputCommand::Quit;
+        }
+
+        if self.auto_pause_on_blur {
+            self.follow_focus();
+        }
+
+        if keys.contains(&self.hotkeys.pause) {
+            if !self.pause_pressed {
+                self.toggle_pause();
+                self.pause_pressed = true;
+            }
+        } else {
+            self.pause_pressed = false;
+        }
+
+        if keys.contains(&Keycode::Up) {
+            if !self.up_pressed {
+                self.set_playback_speed(self.playback_speed() * SPEED_STEP);
+                self.up_pressed = true;
+            }
+        } else {
+            self.up_pressed = false;
+        }
+
+        if keys.contains(&Keycode::Down) {
+            if !self.down_pressed {
+                self.set_playback_speed(self.playback_speed() / SPEED_STEP);
+                self.down_pressed = true;
+            }
+        } else {
+            self.down_pressed = false;
+        }
+
+        if keys.contains(&Keycode::Left) {
+            if !self.left_pressed {
+                self.seek(-SEEK_STEP_MICROS);
+                self.left_pressed = true;
+                return InputCommand::Seek;
+            }
+        } else {
+            self.left_pressed = false;
+        }
+
+        if keys.contains(&Keycode::Right) {
+            if !self.right_pressed {
+                self.seek(SEEK_STEP_MICROS);
+                self.right_pressed = true;
+                return InputCommand::Seek;
+            }
+        } else {
+            self.right_pressed = false;
+        }
+
+        InputCommand::None
+    }
+
+    // Pauses while another window has focus so the inputs don't go to it,
+    // and resumes when focus comes back unless the user paused in the meantime
+    fn follow_focus(&mut self) {
+        let Some(focused) = self.window_has_focus() else {
+            return;
+        };
+
+        if !focused && !self.is_paused() {
+            info!("Game window lost focus, pausing");
+            self.paused.store(true, atomic::Ordering::Relaxed);
+            self.paused_by_blur = true;
+        } else if focused && self.paused_by_blur {
+            info!("Game window focused, resuming");
+            self.paused_by_blur = false;
+            if self.is_paused() {
+                self.paused.store(false, atomic::Ordering::Relaxed);
+            }
+        }
+    }
+
+    // Whether the game window has keyboard focus, None when it can't be told
+    #[cfg(not(any(windows, target_os = "macos")))]
+    fn window_has_focus(&self) -> Option<bool> {
+        let (conn, active_atom) = self.focus_watch.as_ref()?;
+        Some(active_window(conn, *active_atom)? == self.window.id())
+    }
+
+    // Whether the game window has keyboard focus, None when it can't be told
+    #[cfg(windows)]
+    fn window_has_focus(&self) -> Option<bool> {
+        let foreground = unsafe { GetForegroundWindow() };
+        Some(foreground == self.window.id() as usize as HWND)
+    }
+
+    // Whether the game window has keyboard focus, None when it can't be told
+    #[cfg(target_os = "macos")]
+    fn window_has_focus(&self) -> Option<bool> {
+        Some(frontmost_window_id()? == self.window.id())
+    }
+
+    // Jumps the playback position by the given offset from the elapsed time
+    fn seek(&mut self, offset_micros: i64) {
+        let elapsed = self.song_elapsed_micros.load(atomic::Ordering::Relaxed);
+        let target_micros = elapsed.saturating_add_signed(offset_micros) as f64;
+        let speed = self.playback_speed();
+
+        // Walk the tempo map from the start to find the tick at the target time
+        let mut micros_per_tick = self.initial_micros_per_tick();
+        let mut tick = 0;
+        let mut time = 0.0;
+        let mut index = self.events.len();
+        for (i, timed_event) in self.events.iter().enumerate() {
+            let tick_micros = micros_per_tick / speed;
+            let event_time = time + (timed_event.absolute_time - tick) as f64 * tick_micros;
+            if event_time >= target_micros {
+                if tick_micros > 0.0 {
+                    let ticks = ((target_micros - time) / tick_micros) as u64;
+                    tick += ticks;
+                    time += ticks as f64 * tick_micros;
+                }
+                index = i;
+                break;
+            }
+
+            time = event_time;
+            tick = timed_event.absolute_time;
+            if let (TrackEventKind::Meta(midly::MetaMessage::Tempo(tempo)), Some(ticks_per_beat)) =
+                (timed_event.event.kind, self.ticks_per_beat)
+            {
+                micros_per_tick = WebfishingPlayer::tempo_micros_per_tick(tempo, ticks_per_beat);
+            }
+        }
+
+        self.event_index = index;
+        self.current_tick = tick;
+        self.micros_per_tick = micros_per_tick;
+        self.strings_played.fill(false);
+        self.clear_sounding_notes();
+        self.song_elapsed_micros
+            .store(time as u64, atomic::Ordering::Relaxed);
+        self.reset_clock();
+
+        let whole_secs = time as u64 / 1_000_000;
+        info!("Seeked to {:02}:{:02}", whole_secs / 60, whole_secs % 60);
+    }
+
+    // Moves playback to the tick, applying the tempo changes before it
+    fn seek_to_tick(&mut self, target_tick: u64) {
+        let speed = self.playback_speed();
+        let mut micros_per_tick = self.initial_micros_per_tick();
+        let mut tick = 0;
+        let mut time = 0.0;
+        let mut index = self.events.len();
+        for (i, timed_event) in self.events.iter().enumerate() {
+            if timed_event.absolute_time >= target_tick {
+                index = i;
+                break;
+            }
+
+            time += (timed_event.absolute_time - tick) as f64 * micros_per_tick / speed;
+            tick = timed_event.absolute_time;
+            if let (TrackEventKind::Meta(midly::MetaMessage::Tempo(tempo)), Some(ticks_per_beat)) =
+                (timed_event.event.kind, self.ticks_per_beat)
+            {
+                micros_per_tick = WebfishingPlayer::tempo_micros_per_tick(tempo, ticks_per_beat);
+            }
+        }
+        time += target_tick.saturating_sub(tick) as f64 * micros_per_tick / speed;
+
+        self.event_index = index;
+        self.current_tick = target_tick;
+        self.micros_per_tick = micros_per_tick;
+        self.strings_played.fill(false);
+        self.clear_sounding_notes();
+        self.song_elapsed_micros
+            .store(time as u64, atomic::Ordering::Relaxed);
+        self.reset_clock();
+    }
+
+    // The tick each time signature starts on, the bar it starts at counting from 0,
+    // the ticks in a beat and the beats in a bar, starting with 4/4 at tick 0.
+    // Empty with timecode timing
+    fn meter_map(&self) -> Vec<(u64, u64, u64, u64)> {
+        let Some(ticks_per_beat) = self.ticks_per_beat else {
+            return Vec::new();
+        };
+
+        let mut meter_map = vec![(0, 0, ticks_per_beat, 4)];
+        for timed_event in &self.events {
+            if let TrackEventKind::Meta(midly::MetaMessage::TimeSignature(numerator, denominator, _, _)) =
+                timed_event.event.kind
+            {
+                let (start, bar, beat_ticks, beats) = meter_map[meter_map.len() - 1];
+                let tick = timed_event.absolute_time;
+                // A signature partway through a bar starts a new one
+                let bar = bar + (tick - start).div_ceil(beat_ticks * beats);
+                // The denominator is a power of two, 2 for quarter note beats
+                let beat_ticks = (ticks_per_beat * 4)
+                    .checked_shr(denominator as u32)
+                    .unwrap_or_default()
+                    .max(1);
+                let meter = (tick, bar, beat_ticks, numerator.max(1) as u64);
+                if start == tick {
+                    *meter_map.last_mut().unwrap() = meter;
+                } else {
+                    meter_map.push(meter);
+                }
+            }
+        }
+        meter_map
+    }
+
+    /// The tick a bar starts on, counting from bar 1 through every time signature change.
+    /// None for timecode timing, which has no bars
+    pub fn bar_start_tick(&self, bar: u32) -> Option<u64> {
+        let bar = bar.saturating_sub(1) as u64;
+        let meter_map = self.meter_map();
+        let &(start, first_bar, beat_ticks, beats) =
+            meter_map.iter().rev().find(|&&(_, first_bar, _, _)| first_bar <= bar)?;
+        Some(start + (bar - first_bar) * beat_ticks * beats)
+    }
+
+    // The tick each tempo starts on, the song time in microseconds it starts at
+    // and its microseconds per tick, starting with the tempo at tick 0
+    fn tempo_map(&self) -> Vec<(u64, f64, f64)> {
+        let mut tempo_map = vec![(0, 0.0, self.initial_micros_per_tick())];
+        for timed_event in &self.events {
+            if let (TrackEventKind::Meta(midly::MetaMessage::Tempo(tempo)), Some(ticks_per_beat)) =
+                (timed_event.event.kind, self.ticks_per_beat)
+            {
+                let (start, micros, micros_per_tick) = tempo_map[tempo_map.len() - 1];
+                let tick = timed_event.absolute_time;
+                let micros = micros + (tick - start) as f64 * micros_per_tick;
+                tempo_map.push((tick, micros, WebfishingPlayer::tempo_micros_per_tick(tempo, ticks_per_beat)));
+            }
+        }
+        tempo_map
+    }
+
+    // Finds the time in the song a tick is at at normal speed
+    fn millis_at_tick(&self, target_tick: u64) -> u64 {
+        let mut micros_per_tick = self.initial_micros_per_tick();
+        let mut tick = 0;
+        let mut time = 0.0;
+        for timed_event in &self.events {
+            if timed_event.absolute_time >= target_tick {
+                break;
+            }
+
+            time += (timed_event.absolute_time - tick) as f64 * micros_per_tick;
+            tick = timed_event.absolute_time;
+            if let (TrackEventKind::Meta(midly::MetaMessage::Tempo(tempo)), Some(ticks_per_beat)) =
+                (timed_event.event.kind, self.ticks_per_beat)
+            {
+                micros_per_tick = WebfishingPlayer::tempo_micros_per_tick(tempo, ticks_per_beat);
+            }
+        }
+        time += (target_tick - tick) as f64 * micros_per_tick;
+        (time / 1000.0) as u64
+    }
+
+    // Finds the tick at a time in the song at normal speed
+    fn tick_at_millis(&self, millis: u64) -> u64 {
+        let target_micros = millis as f64 * 1000.0;
+        let mut micros_per_tick = self.initial_micros_per_tick();
+        let mut tick = 0;
+        let mut time = 0.0;
+        for timed_event in &self.events {
+            let event_time = time + (timed_event.absolute_time - tick) as f64 * micros_per_tick;
+            if event_time >= target_micros {
+                break;
+            }
+
+            time = event_time;
+            tick = timed_event.absolute_time;
+            if let (TrackEventKind::Meta(midly::MetaMessage::Tempo(tempo)), Some(ticks_per_beat)) =
+                (timed_event.event.kind, self.ticks_per_beat)
+            {
+                micros_per_tick = WebfishingPlayer::tempo_micros_per_tick(tempo, ticks_per_beat);
+            }
+        }
+
+        if micros_per_tick <= 0.0 {
+            return tick;
+        }
+        tick + ((target_micros - time) / micros_per_tick) as u64
+    }
+
+    fn playback_speed(&self) -> f64 {
+        f64::from_bits(self.playback_speed.load(atomic::Ordering::Relaxed))
+    }
+
+    // The playback speed eased in from normal speed while the speed ramp runs
+    fn ramped_speed(&self) -> f64 {
+        let speed = self.playback_speed();
+        let (Some(ramp), Some(ramp_start)) = (self.speed_ramp, self.ramp_start_micros) else {
+            return speed;
+        };
+        if ramp.is_zero() {
+            return speed;
+        }
+
+        let elapsed = self.song_elapsed_micros.load(atomic::Ordering::Relaxed);
+        let progress = elapsed.saturating_sub(ramp_start) as f64 / ramp.as_micros() as f64;
+        if progress >= 1.0 {
+            return speed;
+        }
+        1.0 + (speed - 1.0) * progress
+    }
+
+    fn set_playback_speed(&self, speed: f64) {
+        let speed = speed.clamp(MIN_SPEED, MAX_SPEED);
+        self.playback_speed
+            .store(speed.to_bits(), atomic::Ordering::Relaxed);
+        info!("Playback speed: {:.2}x", speed);
+    }
+
+    // The tick duration before any tempo events, fixed for timecode timing
+    fn initial_micros_per_tick(&self) -> f64 {
+        match self.source.header().timing {
+            // Files are played at the default tempo until their first tempo change
+            midly::Timing::Metrical(ticks_per_beat) => DEFAULT_TEMPO as f64 / ticks_per_beat.as_int() as f64,
+            midly::Timing::Timecode(fps, subframe) => {
+                WebfishingPlayer::timecode_micros_per_tick(fps, subframe)
+            }
+        }
+    }
+
+    fn apply_tempo(&mut self, tempo: u24, track: u32) {
+        // Tempo events don't apply to timecode timing
+        if let Some(ticks_per_beat) = self.ticks_per_beat {
+            self.micros_per_tick = WebfishingPlayer::tempo_micros_per_tick(tempo, ticks_per_beat);
+            info!(
+                "Tempo change: {:.3}µs per tick - track {}",
+                self.micros_per_tick, track
+            );
+        }
+    }
+
+    // The length of a beat at the song's first tempo
+    fn initial_beat_micros(&self) -> u64 {
+        if self.ticks_per_beat.is_none() {
+            // Timecode timing has no tempo
+            return DEFAULT_TEMPO;
+        }
+
+        self.events
+            .iter()
+            .find_map(|timed_event| match timed_event.event.kind {
+                TrackEventKind::Meta(midly::MetaMessage::Tempo(tempo)) => Some(tempo.as_int() as u64),
+                _ => None,
+            })
+            .unwrap_or(DEFAULT_TEMPO)
+    }
+
+    // Counts down the beats before the song starts, returns false if it was stopped
+    fn count_in(&self, device_state: &DeviceState) -> bool {
+        let beat_micros = self.initial_beat_micros() as f64 / self.playback_speed();
+        let beat = Duration::from_micros(beat_micros as u64);
+        for beat_num in (1..=self.count_in_beats).rev() {
+            println!("{}...", beat_num);
+            let beat_start = Instant::now();
+            while beat_start.elapsed() < beat {
+                if self.should_stop() || device_state.get_keys().contains(&self.hotkeys.quit) {
+                    return false;
+                }
+                sleep(Duration::from_millis(10).min(beat.saturating_sub(beat_start.elapsed())));
+            }
+        }
+        true
+    }
+
+    /// Lets the user line up the mouse with the first and last fret and derives the calibration from them
+    pub fn calibrate(&mut self) -> Result<Calibration, PlayerError> {
+        let device_state = DeviceState::new();
+        println!("Use the arrow keys to move the mouse onto the fret, hold shift for bigger steps and press enter to confirm");
+
+        println!("Line up the mouse with the open lowest string");
+        let (first_x, first_y) = self.calibration.fret_position(self.window, 0, 0);
+        let (first_x, first_y) = self.nudge_mouse(&device_state, first_x, first_y)?;
+
+        println!("Line up the mouse with fret {} of the highest string", FRET_COUNT - 1);
+        let last_fret = FRET_COUNT as i32 - 1;
+        let highest_string = self.string_count() - 1;
+        let (last_x, last_y) = self.calibration.fret_position(self.window, highest_string, last_fret);
+        let (last_x, last_y) = self.nudge_mouse(&device_state, last_x, last_y)?;
+
+        // Convert the positions back to the reference window size
+        let scale_x = self.window.width() as f32 / self.calibration.reference_width;
+        let scale_y = self.window.height() as f32 / self.calibration.reference_height;
+        // The top row is the last fret when the frets are inverted
+        let (top_y, bottom_y) = if self.calibration.invert_frets {
+            (last_y, first_y)
+        } else {
+            (first_y, last_y)
+        };
+        self.calibration.left = (first_x - self.window.x()) as f32 / scale_x;
+        self.calibration.top = (top_y - self.window.y()) as f32 / scale_y;
+        self.calibration.string_spacing = (last_x - first_x) as f32 / highest_string.max(1) as f32 / scale_x;
+        self.calibration.fret_spacing = (bottom_y - top_y) as f32 / last_fret as f32 / scale_y;
+
+        info!("Calibration: {:?}", self.calibration);
+        Ok(self.calibration)
+    }
+
+    // Moves the mouse with the arrow keys until enter is pressed
+    fn nudge_mouse(
+        &mut self,
+        device_state: &DeviceState,
+        mut x: i32,
+        mut y: i32,
+    ) -> Result<(i32, i32), PlayerError> {
+        // Keys already held down don't count until they are pressed again
+        let mut held_keys = device_state.get_keys();
+        loop {
+            self.enigo()?.move_mouse(x, y, Coordinate::Abs)?;
+            sleep(Duration::from_millis(10));
+
+            let keys = device_state.get_keys();
+            let step = if keys.contains(&Keycode::LShift) || keys.contains(&Keycode::RShift) {
+                10
+            } else {
+                1
+            };
+            for key in keys.iter().filter(|key| !held_keys.contains(key)) {
+                match key {
+                    Keycode::Left => x -= step,
+                    Keycode::Right => x += step,
+                    Keycode::Up => y -= step,
+                    Keycode::Down => y += step,
+                    Keycode::Enter => return Ok((x, y)),
+                    _ => {}
+                }
+            }
+            held_keys = keys;
+        }
+    }
+
+    /// Translates the whole song into the actions `play()` would perform without sending any input
+    pub fn actions(&mut self) -> Vec<PlayerAction> {
+        let saved_string_positions = self.cur_string_positions.clone();
+        let saved_usage_times = self.last_string_usage_time.clone();
+        self.rewind();
+
+        let mut actions = Vec::new();
+        // Reset the guitar to all open string
+        self.open_strings(&mut actions);
+
+        while self.event_index < self.events.len() {
+            let absolute_time = self.events[self.event_index].absolute_time;
+            let wait_ticks = absolute_time - self.current_tick;
+            if wait_ticks > 0 {
+                self.strings_played.fill(false);
+                actions.push(PlayerAction::Wait(Duration::from_micros(
+                    (wait_ticks as f64 * self.micros_per_tick / self.playback_speed()) as u64,
+                )));
+            }
+            self.current_tick = absolute_time;
+
+            let group = self.next_tick_group();
+            actions.extend(self.tick_actions(&group));
+        }
+
+        // Leave the player ready to play the song
+        self.cur_string_positions = saved_string_positions;
+        self.last_string_usage_time = saved_usage_times;
+        self.rewind();
+
+        actions
+    }
+
+    /// Writes the song as ASCII tab with the strings and frets `play()` would use,
+    /// each column is a moment strings are strummed and S marks where the player sings
+    pub fn export_tab(&mut self, mut writer: impl io::Write) -> io::Result<()> {
+        // Keeps the lines of each system within a terminal width
+        const LINE_WIDTH: usize = 80;
+
+        let string_count = self.string_notes.len();
+        let empty_column = (vec![None; string_count], false);
+
+        // Group the strums between waits into columns
+        let mut frets = vec![0; string_count];
+        let mut columns: Vec<(Vec<Option<i32>>, bool)> = Vec::new();
+        let mut column = empty_column.clone();
+        for action in self.actions() {
+            match action {
+                // The button past the highest string opens all of them
+                PlayerAction::SetFret { string, fret } if string as usize >= string_count => frets.fill(fret),
+                PlayerAction::SetFret { string, fret } => frets[string as usize] = fret,
+                PlayerAction::Strum { string } => {
+                    column.0[string as usize] = Some(frets[string as usize]);
+                }
+                PlayerAction::StrumChord { strings } => {
+                    for string in strings {
+                        column.0[string as usize] = Some(frets[string as usize]);
+                    }
+                }
+                PlayerAction::Sing { .. } | PlayerAction::SingHold { .. } => column.1 = true,
+                PlayerAction::SingRelease => {}
+                PlayerAction::Wait(_) => {
+                    if column != empty_column {
+                        columns.push(column);
+                        column = empty_column.clone();
+                    }
+                }
+            }
+        }
+        if column != empty_column {
+            columns.push(column);
+        }
+
+        // Each column is as wide as its widest fret
+        let widths: Vec<usize> = columns
+            .iter()
+            .map(|(frets, _)| {
+                frets
+                    .iter()
+                    .flatten()
+                    .map(|fret| fret.to_string().len())
+                    .max()
+                    .unwrap_or(1)
+            })
+            .collect();
+
+        let mut system_start = 0;
+        while system_start < columns.len() {
+            // Fit as many columns as the line width allows
+            let mut width = 3;
+            let mut system_end = system_start;
+            while system_end < columns.len() {
+                if width + widths[system_end] + 1 > LINE_WIDTH && system_end > system_start {
+                    break;
+                }
+                width += widths[system_end] + 1;
+                system_end += 1;
+            }
+
+            let mut lines = vec![String::new(); string_count + 1];
+            // The highest string goes on top
+            for (line, string) in lines.iter_mut().zip((0..string_count).rev()) {
+                let open_note = self.string_notes[string][0];
+                *line = format!("{:<2}|", NOTE_NAMES[open_note as usize % 12]);
+            }
+            lines[string_count] = "   ".to_string();
+
+            for ((frets, sing), &column_width) in columns[system_start..system_end]
+                .iter()
+                .zip(&widths[system_start..system_end])
+            {
+                for (line, string) in lines.iter_mut().zip((0..string_count).rev()) {
+                    let cell = frets[string].map_or(String::new(), |fret| fret.to_string());
+                    line.push_str(&format!("{:-<1$}-", cell, column_width));
+                }
+                let marker = if *sing { "S" } else { "" };
+                lines[string_count].push_str(&format!("{:<1$} ", marker, column_width));
+            }
+
+            for line in &mut lines[..string_count] {
+                line.push('|');
+            }
+            for line in &lines {
+                writeln!(writer, "{}", line.trim_end())?;
+            }
+            writeln!(writer)?;
+            system_start = system_end;
+        }
+
+        Ok(())
+    }
+
+    /// Writes a WAV file of plain tones at the notes and times `play()` would strum,
+    /// after shifting, mapping and dropping, to hear the result without the game open
+    pub fn render_preview(&mut self, path: impl AsRef<Path>) -> io::Result<()> {
+        let string_count = self.string_notes.len();
+
+        // When each strum starts in microseconds and the string and note it plays
+        let mut frets = vec![0; string_count];
+        let mut plucks: Vec<(u64, usize, u8)> = Vec::new();
+        for (time, action) in self.timed_actions() {
+            let strings = match action {
+                PlayerAction::SetFret { string, fret } if string as usize >= string_count => {
+                    frets.fill(fret);
+                    continue;
+                }
+                PlayerAction::SetFret { string, fret } => {
+                    frets[string as usize] = fret;
+                    continue;
+                }
+                PlayerAction::Strum { string } => vec![string],
+                PlayerAction::StrumChord { strings } => strings,
+                _ => continue,
+            };
+            for string in strings {
+                let string = string as usize;
+                if let Some(&note) = self.string_notes[string].get(frets[string] as usize) {
+                    plucks.push((time, string, note));
+                }
+            }
+        }
+
+        let sample_at = |micros: u64| (micros * PREVIEW_SAMPLE_RATE as u64 / 1_000_000) as usize;
+        let ring_samples = sample_at(PREVIEW_RING.as_micros() as u64);
+        let length = plucks.last().map_or(0, |&(time, _, _)| sample_at(time) + ring_samples);
+        let mut samples = vec![0.0f32; length];
+        for (index, &(time, string, note)) in plucks.iter().enumerate() {
+            // A string stops ringing when it's strummed again
+            let start = sample_at(time);
+            let end = plucks[index + 1..]
+                .iter()
+                .find(|&&(_, next_string, _)| next_string == string)
+                .map_or(start + ring_samples, |&(next_time, _, _)| sample_at(next_time))
+                .min(start + ring_samples);
+
+            let frequency = 440.0 * 2f32.powf((note as f32 - 69.0) / 12.0);
+            for (offset, sample) in samples[start..end].iter_mut().enumerate() {
+                let t = offset as f32 / PREVIEW_SAMPLE_RATE as f32;
+                *sample += 0.2 * (-3.0 * t).exp() * (2.0 * std::f32::consts::PI * frequency * t).sin();
+            }
+        }
+
+        // 16-bit mono PCM
+        let data_size = (samples.len() * 2) as u32;
+        let mut writer = io::BufWriter::new(File::create(path)?);
+        writer.write_all(b"RIFF")?;
+        writer.write_all(&(36 + data_size).to_le_bytes())?;
+        writer.write_all(b"WAVEfmt ")?;
+        writer.write_all(&16u32.to_le_bytes())?;
+        writer.write_all(&1u16.to_le_bytes())?;
+        writer.write_all(&1u16.to_le_bytes())?;
+        writer.write_all(&PREVIEW_SAMPLE_RATE.to_le_bytes())?;
+        writer.write_all(&(PREVIEW_SAMPLE_RATE * 2).to_le_bytes())?;
+        writer.write_all(&2u16.to_le_bytes())?;
+        writer.write_all(&16u16.to_le_bytes())?;
+        writer.write_all(b"data")?;
+        writer.write_all(&data_size.to_le_bytes())?;
+        for sample in samples {
+            let sample = (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16;
+            writer.write_all(&sample.to_le_bytes())?;
+        }
+        writer.flush()
+    }
+
+    /// The actions `play()` would perform with the microsecond of the song each one is due at
+    pub fn timed_actions(&mut self) -> Vec<(u64, PlayerAction)> {
+        let mut time = 0;
+        let mut timed_actions = Vec::new();
+        for action in self.actions() {
+            match action {
+                PlayerAction::Wait(duration) => time += duration.as_micros() as u64,
+                action => timed_actions.push((time, action)),
+            }
+        }
+        timed_actions
+    }
+
+    /// Returns the player to how it was when created, so the same song can be played again.
+    /// Settings changed through the controls, like the speed, are kept
+    pub fn reset(&mut self) {
+        self.prepare_events();
+        self.rewind();
+        self.held_sing = None;
+        self.paused_by_blur = false;
+        self.paused.store(false, atomic::Ordering::Relaxed);
+
+        // For each string initialize the cur pos as 0
+        for i in 0..self.string_count() {
+            self.cur_string_positions.insert(i, 0);
+        }
+        self.last_string_usage_time.fill(Instant::now());
+    }
+
+    /// Plays the song from the start, it's left where playback stopped
+    /// and can be played again, or `reset()` first to also clear the guitar state
+    pub fn play(&mut self) -> Result<(), PlayerError> {
+        self.timing_report = TimingReport::default();
+        self.mapping_issues.clear();
+        self.interrupted_at = None;
+        let result = self.play_song();
+        // The song can be stopped while the sing key is held for a note
+        let released = self.release_sing_key();
+
+        if self.report_timing && self.timing_report.input_ticks > 0 {
+            let report = self.timing_report;
+            info!(
+                "{} of {} ticks sent late, {:.1}ms behind on average and {:.1}ms at most",
+                report.late_ticks,
+                report.input_ticks,
+                report.average_lateness().as_secs_f64() * 1000.0,
+                report.max_lateness.as_secs_f64() * 1000.0
+            );
+        }
+
+        if !self.mapping_issues.is_empty() {
+            let count = |reason| self.mapping_issues.iter().filter(|issue| issue.reason == reason).count();
+            warn!(
+                "{} notes dropped ({} out of range, {} with no free string, {} percussion, {} over the rate limit)",
+                self.mapping_issues.len(),
+                count(MappingReason::OutOfRange),
+                count(MappingReason::StringsExhausted),
+                count(MappingReason::Percussion),
+                count(MappingReason::RateLimited)
+            );
+        }
+
+        result.and(released)
+    }
+
+    /// Blocks until the start hotkey is pressed, `play()` only calls this when the player
+    /// was created with `wait_for_user`. Returns false when the player was stopped while waiting
+    pub fn wait_for_start(&mut self, device_state: &DeviceState) -> Result<bool, PlayerError> {
+        // Attempt to press space in-case the user's OS requires a permission pop-up for input
+        if !self.dry_run {
+            self.enigo()?.key(Key::Space, Click)?;
+        }
+
+        #[cfg(feature = "silent_input")]
+        println!("Press {} to start playing", self.hotkeys.start);
+        #[cfg(not(feature = "silent_input"))]
+        println!("Tab over to the game and press {} to start playing", self.hotkeys.start);
+        let waiting_since = Instant::now();
+        let mut key_seen = false;
+        let mut warned = false;
+        loop {
+            let keys = device_state.get_keys();
+            if keys.contains(&self.hotkeys.start) {
+                return Ok(true);
+            }
+            if self.should_stop() {
+                return Ok(false);
+            }
+
+            key_seen |= !keys.is_empty();
+            if !key_seen && !warned && waiting_since.elapsed() >= NO_KEYS_WARNING {
+                warned = true;
+                println!("{}", NO_KEYS_HELP);
+            }
+            sleep(POLL_INTERVAL);
+        }
+    }
+
+    /// How far behind schedule the inputs fell during the last `play()`
+    pub fn timing_report(&self) -> TimingReport {
+        self.timing_report
+    }
+
+    /// Where the last `play()` was stopped by the quit hotkey or `stop()`,
+    /// None when it played to the end
+    pub fn interrupted_at(&self) -> Option<PlaybackState> {
+        self.interrupted_at
+    }
+
+    /// The current playback position
+    pub fn playback_state(&self) -> PlaybackState {
+        PlaybackState {
+            tick: self.current_tick,
+            loops: self.loops_played,
+            elapsed_micros: self.song_elapsed_micros.load(atomic::Ordering::Relaxed),
+        }
+    }
+
+    // Keeps the position playback was stopped at so it can be resumed
+    fn interrupt(&mut self) {
+        self.interrupted_at = Some(self.playback_state());
+        info!("Song interrupted");
+    }
+
+    /// The notes dropped during the last `play()` and why
+    pub fn mapping_issues(&self) -> &[MappingIssue] {
+        &self.mapping_issues
+    }
+
+    fn record_issue(&mut self, midi_note: MidiNote, reason: MappingReason) {
+        self.mapping_issues.push(MappingIssue {
+            note: midi_note.note,
+            tick: self.current_tick,
+            track: midi_note.track,
+            reason,
+        });
+    }
+
+    fn play_song(&mut self) -> Result<(), PlayerError> {
+        let device_state = DeviceState::new();
+
+        println!(
+            "{} to stop the song, {} to pause/play, up/down arrows to change speed, left/right arrows to seek",
+            self.hotkeys.quit, self.hotkeys.pause
+        );
+        if self.dry_run {
+            info!("Dry run, no input will be sent to the game");
+        }
+
+        if self.wait_for_user {
+            if !self.wait_for_start(&device_state)? {
+                self.interrupted_at = self.resume_from;
+                info!("Song interrupted");
+                return Ok(());
+            }
+        }
+        else {
+            // Wait to start at a certain timestamp if provided
+            if let Some(start_time) = self.start_time {
+                let current_time = SystemTime::now()
+                    .duration_since(SystemTime::UNIX_EPOCH)
+                    .expect("Time went backwards")
+                    .as_millis();
+
+                if u128::from(start_time) > current_time {
+                    let wait_duration = Duration::from_millis((start_time as u128 - current_time) as u64);
+                    let wait_seconds = wait_duration.as_secs();
+
+                    #[cfg(feature = "silent_input")]
+                    println!("Starting playback in {} seconds...", wait_seconds);
+                    #[cfg(not(feature = "silent_input"))]
+                    println!("Tab over to the game, starting playback in {} seconds...", wait_seconds);
+
+                    std::thread::sleep(wait_duration);
+                }
+            }
+        }
+
+        if !self.count_in(&device_state) {
+            // Nothing was played yet, so a resumed song can be resumed from the same place again
+            self.interrupted_at = self.resume_from;
+            info!("Song interrupted");
+            return Ok(());
+        }
+
+        // Reset the guitar to all open string
+        let mut reset_actions = Vec::new();
+        self.open_strings(&mut reset_actions);
+        self.perform_actions(&reset_actions)?;
+
+        let final_tick = self.events.last().map_or(0, |timed_event| timed_event.absolute_time);
+        let mut first_pass = true;
+        self.loops_played = 0;
+        loop {
+            // Start a new loop for playback
+            self.rewind();
+            if let Some((loop_start, _)) = self.loop_ticks {
+                self.seek_to_tick(loop_start);
+            }
+            // Only the first pass starts from the offset, the tempo up to it still applies
+            if let Some(state) = self.resume_from.filter(|_| first_pass) {
+                self.seek_to_tick(state.tick);
+                self.loops_played = state.loops;
+                let whole_secs = self.song_elapsed_micros.load(atomic::Ordering::Relaxed) / 1_000_000;
+                info!("Resuming from {:02}:{:02}", whole_secs / 60, whole_secs % 60);
+            } else if let Some(offset) = self.start_offset_ticks.filter(|_| first_pass) {
+                self.seek_to_tick(offset);
+            }
+            // The speed ramp also only runs once, from wherever the song starts
+            self.ramp_start_micros = first_pass.then(|| self.song_elapsed_micros.load(atomic::Ordering::Relaxed));
+            first_pass = false;
+
+            let pb = match self.multi {
+                Some(multi) => multi.add(ProgressBar::new(final_tick)),
+                None => ProgressBar::hidden(),
+            };
+            let paused = Arc::clone(&self.paused);
+            let elapsed = Arc::clone(&self.song_elapsed_micros);
+            let playback_speed = Arc::clone(&self.playback_speed);
+            let remaining_speed = Arc::clone(&self.playback_speed);
+            let tempo_map = self.tempo_map();
+            let meter_map = self.meter_map();
+            let loop_millis = self.loop_millis;
+
+            let style = ProgressStyle::with_template(&self.progress_template).unwrap_or_else(|e| {
+                warn!("Invalid progress bar template, using the default: {}", e);
+                ProgressStyle::with_template(PROGRESS_TEMPLATE).unwrap()
+            });
+            pb.set_style(
+                style
+                    .with_key("paused", move |_: &ProgressState, w: &mut dyn Write| {
+                        let ch = if paused.load(atomic::Ordering::Relaxed) {
+                            "⏸"
+                        } else {
+                            "▶"
+                        };
+                        write!(w, "{}", ch).unwrap()
+                    })
+                    .with_key("elapsed", move |_: &ProgressState, w: &mut dyn Write| {
+                        let micros = elapsed.load(atomic::Ordering::Relaxed);
+                        let duration = Duration::from_micros(micros);
+                        let whole_secs = duration.as_secs();
+                        let mins = whole_secs / 60;
+                        let secs = whole_secs % 60;
+                        write!(w, "{:02}:{:02}", mins, secs).unwrap()
+                    })
+                    .with_key("remaining", move |state: &ProgressState, w: &mut dyn Write| {
+                        // Time left at normal speed from the tempo at each tick, scaled by the live speed
+                        let micros_at = |tick: u64| {
+                            let index = tempo_map.partition_point(|&(start, _, _)| start <= tick);
+                            let (start, micros, micros_per_tick) = tempo_map[index.saturating_sub(1)];
+                            micros + (tick - start) as f64 * micros_per_tick
+                        };
+                        let final_tick = state.len().unwrap_or_default();
+                        let remaining = (micros_at(final_tick) - micros_at(state.pos().min(final_tick))).max(0.0);
+                        let speed = f64::from_bits(remaining_speed.load(atomic::Ordering::Relaxed));
+                        let whole_secs = (remaining / speed / 1_000_000.0) as u64;
+                        write!(w, "{:02}:{:02}", whole_secs / 60, whole_secs % 60).unwrap()
+                    })
+                    .with_key("position", move |state: &ProgressState, w: &mut dyn Write| {
+                        // Timecode timing has no beats to count
+                        if meter_map.is_empty() {
+                            return write!(w, "-").unwrap();
+                        }
+                        let tick = state.pos();
+                        let index = meter_map.partition_point(|&(start, ..)| start <= tick);
+                        let (start, bar, beat_ticks, beats) = meter_map[index.saturating_sub(1)];
+                        let beat = (tick - start) / beat_ticks;
+                        write!(w, "{}:{}", bar + beat / beats + 1, beat % beats + 1).unwrap()
+                    })
+                    .with_key("speed", move |_: &ProgressState, w: &mut dyn Write| {
+                        let speed = f64::from_bits(playback_speed.load(atomic::Ordering::Relaxed));
+                        write!(w, "{:.2}x", speed).unwrap()
+                    })
+                    .with_key("loop", move |_: &ProgressState, w: &mut dyn Write| {
+                        if let Some((loop_start, loop_end)) = loop_millis {
+                            let (start_secs, end_secs) = (loop_start / 1000, loop_end / 1000);
+                            write!(
+                                w,
+                                " Loop: {:02}:{:02}-{:02}:{:02}",
+                                start_secs / 60,
+                                start_secs % 60,
+                                end_secs / 60,
+                                end_secs % 60
+                            )
+                            .unwrap()
+                        }
+                    }),
+            );
+
+            'playback: loop {
+                self.poll_stream();
+                match self.check_inputs(&device_state) {
+                    InputCommand::Quit => {
+                        self.interrupt();
+                        return Ok(());
+                    }
+                    InputCommand::Seek => {
+                        self.update_progress(&pb);
+                        continue 'playback;
+                    }
+                    InputCommand::None => {}
+                }
+
+                // Jump back to the start of the loop section once its end is reached
+                if let Some((loop_start, loop_end)) = self.loop_ticks {
+                    if self.current_tick >= loop_end || self.event_index >= self.events.len() {
+                        self.seek_to_tick(loop_start);
+                        self.loops_played += 1;
+                        self.update_progress(&pb);
+                        continue 'playback;
+                    }
+                } else if self.event_index >= self.events.len() {
+                    if !self.streaming {
+                        break;
+                    }
+                    // Wait for the stream like a pause, so the time waited isn't caught up on
+                    sleep(POLL_INTERVAL);
+                    self.reset_clock();
+                    continue 'playback;
+                }
+
+                // Wait while paused
+                if self.is_paused() {
+                    self.update_progress(&pb);
+                    self.release_sing_key()?;
+                    while self.is_paused() {
+                        sleep(Duration::from_millis(100));
+                        match self.check_inputs(&device_state) {
+                            InputCommand::Quit => {
+                                self.interrupt();
+                                return Ok(());
+                            }
+                            InputCommand::Seek => {
+                                self.update_progress(&pb);
+                                continue 'playback;
+                            }
+                            InputCommand::None => {}
+                        }
+                    }
+                    // Don't try to catch up on the time spent paused
+                    self.reset_clock();
+                }
+
+                let absolute_time = self.events[self.event_index].absolute_time;
+                if absolute_time > self.current_tick {
+                    self.strings_played.fill(false);
+                }
+
+                // Stop waiting at the end of the loop section
+                let wait_until = match self.loop_ticks {
+                    Some((_, loop_end)) => absolute_time.min(loop_end),
+                    None => absolute_time,
+                };
+
+                // Sleep until the event is due on the playback clock, in short chunks
+                // so inputs are still checked and the progress bar stays smooth.
+                // Tempo changes are events of their own so a wait never spans one,
+                // the new tempo applies from its exact tick
+                let mut from_tick = self.current_tick;
+                let mut from_micros = self.song_elapsed_micros.load(atomic::Ordering::Relaxed);
+                let mut tick_micros = self.micros_per_tick / self.ramped_speed();
+                loop {
+                    let now_micros = self.clock_micros();
+                    let ticks_passed = if tick_micros > 0.0 {
+                        (now_micros.saturating_sub(from_micros) as f64 / tick_micros) as u64
+                    } else {
+                        u64::MAX
+                    };
+                    self.current_tick = from_tick.saturating_add(ticks_passed).min(wait_until);
+                    let tick_elapsed = from_micros + ((self.current_tick - from_tick) as f64 * tick_micros) as u64;
+                    self.song_elapsed_micros
+                        .store(tick_elapsed, atomic::Ordering::Relaxed);
+                    self.update_progress(&pb);
+                    if self.current_tick >= wait_until {
+                        break;
+                    }
+
+                    let target_micros = from_micros + ((wait_until - from_tick) as f64 * tick_micros) as u64;
+                    let remaining = target_micros.saturating_sub(now_micros);
+                    sleep(Duration::from_micros(remaining).min(POLL_INTERVAL));
+
+                    // Check for inputs during the wait
+                    match self.check_inputs(&device_state) {
+                        InputCommand::Quit => {
+                            self.interrupt();
+                            return Ok(());
+                        }
+                        InputCommand::Seek => {
+                            self.update_progress(&pb);
+                            continue 'playback;
+                        }
+                        InputCommand::None => {}
+                    }
+
+                    if self.is_paused() {
+                        continue 'playback;
+                    }
+
+                    // Keep the time already waited when the speed changes or ramps
+                    let new_tick_micros = self.micros_per_tick / self.ramped_speed();
+                    if new_tick_micros != tick_micros {
+                        from_tick = self.current_tick;
+                        from_micros = tick_elapsed;
+                        tick_micros = new_tick_micros;
+                    }
+                }
+
+                if self.current_tick < absolute_time {
+                    continue 'playback;
+                }
+
+                let group = self.next_tick_group();
+                let actions = self.tick_actions(&group);
+                if !actions.is_empty() {
+                    // The offset is from the tick's exact time and the clock isn't moved,
+                    // so offsets don't add up over the song
+                    let due_micros = self.song_elapsed_micros.load(atomic::Ordering::Relaxed)
+                        + self.humanize_offset(&group);
+                    sleep(Duration::from_micros(due_micros.saturating_sub(self.clock_micros())));
+
+                    // Slow inputs on earlier ticks push back the ones after them
+                    let lateness = self.clock_micros().saturating_sub(due_micros);
+                    self.timing_report.record(Duration::from_micros(lateness));
+                }
+                self.perform_actions(&actions)?;
+
+                self.update_progress(&pb);
+            }
+
+            pb.finish();
+            if let Some(multi) = self.multi {
+                multi.remove(&pb);
+            }
+
+            if self.loop_midi {
+                self.loops_played += 1;
+                info!("Looping the MIDI playback (Hold {} to stop)", self.hotkeys.quit);
+            } else {
+                break;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn tick_actions(&mut self, group: &[TimedEvent]) -> Vec<PlayerAction> {
+        let mut notes = Vec::new();
+        let mut restrums = Vec::new();
+        let mut actions = Vec::new();
+        for timed_event in group {
+            if timed_event.restrum {
+                if let TrackEventKind::Midi {
+                    channel,
+                    message: midly::MidiMessage::NoteOn { key, .. },
+                } = timed_event.event.kind
+                {
+                    restrums.push((channel.as_int(), key.as_int()));
+                }
+                continue;
+            }
+
+            match timed_event.note_event() {
+                Some(NoteEvent::Start { channel, key, vel }) => {
+                    if let Some(note) = self.note_start(channel, key, vel, timed_event.track) {
+                        notes.push(note);
+                    }
+                    continue;
+                }
+                Some(NoteEvent::End { channel, key }) => {
+                    self.note_end((channel, key), &mut actions);
+                    continue;
+                }
+                None => {}
+            }
+
+            match timed_event.event.kind {
+                TrackEventKind::Meta(midly::MetaMessage::Tempo(tempo)) => {
+                    self.apply_tempo(tempo, timed_event.track);
+                }
+                TrackEventKind::Midi {
+                    channel,
+                    message: midly::MidiMessage::PitchBend { bend },
+                } if self.interpret_pitch_bend => {
+                    self.bend_channel(channel.as_int(), bend, &mut actions);
+                }
+                TrackEventKind::Midi {
+                    channel,
+                    message: midly::MidiMessage::Controller { controller, value },
+                } if self.interpret_cc && controller.as_int() == SUSTAIN_CONTROLLER => {
+                    self.set_sustain(channel.as_int(), value.as_int() >= 64);
+                }
+                TrackEventKind::Midi {
+                    channel,
+                    message: midly::MidiMessage::Controller { controller, value },
+                } if self.vibrato_from_modwheel && controller.as_int() == MODULATION_CONTROLLER => {
+                    self.modulation[channel.as_int() as usize] = value.as_int();
+                }
+                _ => {}
+            }
+        }
+
+        let mut notes = self.limit_note_rate(notes);
+        // Notes are given strings in order, so the ones dropped when they run out are the least important
+        self.sort_by_priority(&mut notes);
+
+        let highest_note = notes.iter().map(|note| note.note).max().unwrap_or_default();
+        if self.chord_mode {
+            actions.extend(self.chord_actions(&notes, highest_note));
+        } else {
+            for note in notes {
+                actions.extend(self.note_actions(note, highest_note));
+            }
+        }
+
+        // Only re-strum notes that still have their string and weren't just played over
+        for note in restrums {
+            if let Some(&string) = self.active_notes.get(&note) {
+                if !self.strings_played[string as usize] {
+                    self.strings_played[string as usize] = true;
+                    actions.push(PlayerAction::Strum { string });
+                }
+            }
+        }
+        actions
+    }
+
+    // The note to find a string for when it starts, None when it's skipped
+    fn note_start(&mut self, channel: u8, key: u8, vel: u8, track: u32) -> Option<MidiNote> {
+        // Ghost notes would only take strings away from the louder notes
+        if vel < self.min_velocity {
+            return None;
+        }
+        // Drum notes have no pitch to play
+        if self.skip_percussion && channel == PERCUSSION_CHANNEL {
+            let drum_note = MidiNote {
+                note: key,
+                track,
+                channel,
+                key,
+                velocity: vel,
+            };
+            self.record_issue(drum_note, MappingReason::Percussion);
+            return None;
+        }
+
+        // A held note under the sustain pedal is still ringing
+        if self.sustain[channel as usize] && self.active_notes.contains_key(&(channel, key)) {
+            return None;
+        }
+
+        let note = self.shifted_note(key, track as usize) as i16 + self.pitch_bends[channel as usize] as i16;
+        Some(MidiNote {
+            note: note.clamp(0, 127) as u8,
+            track,
+            channel,
+            key,
+            velocity: vel,
+        })
+    }
+
+    // Keeps the notes that fit in the notes per second limit, the strategy picks which don't
+    fn limit_note_rate(&mut self, mut notes: Vec<MidiNote>) -> Vec<MidiNote> {
+        let Some(max_notes) = self.max_notes_per_second else {
+            return notes;
+        };
+
+        let now = Instant::now();
+        while self
+            .recent_note_starts
+            .front()
+            .is_some_and(|&start| now.duration_since(start) >= RATE_WINDOW)
+        {
+            self.recent_note_starts.pop_front();
+        }
+        let used = self.recent_note_starts.len();
+        let room = (max_notes as usize).saturating_sub(used);
+
+        let dropped = match self.rate_limit_strategy {
+            RateLimitStrategy::DropQuietest => WebfishingPlayer::drop_quietest(&mut notes, room),
+            RateLimitStrategy::DropOldest => {
+                self.pending_notes.extend(notes.drain(..).map(|note| (note, now)));
+                let mut dropped = Vec::new();
+                while self
+                    .pending_notes
+                    .front()
+                    .is_some_and(|&(_, due)| now.duration_since(due) > MAX_NOTE_WAIT)
+                {
+                    dropped.extend(self.pending_notes.pop_front().map(|(note, _)| note));
+                }
+                let ready = room.min(self.pending_notes.len());
+                notes.extend(self.pending_notes.drain(..ready).map(|(note, _)| note));
+                dropped
+            }
+            RateLimitStrategy::ThinToBeat => {
+                let on_beat = self
+                    .ticks_per_beat
+                    .is_none_or(|ticks_per_beat| self.current_tick.is_multiple_of(ticks_per_beat));
+                if !on_beat && used * 2 >= max_notes as usize {
+                    std::mem::take(&mut notes)
+                } else {
+                    WebfishingPlayer::drop_quietest(&mut notes, room)
+                }
+            }
+        };
+
+        for note in dropped {
+            self.record_issue(note, MappingReason::RateLimited);
+        }
+        self.recent_note_starts.extend(notes.iter().map(|_| now));
+        notes
+    }
+
+    // Leaves the loudest notes that fit in the room, keeping their order, and returns the rest
+    fn drop_quietest(notes: &mut Vec<MidiNote>, room: usize) -> Vec<MidiNote> {
+        if notes.len() <= room {
+            return Vec::new();
+        }
+
+        let mut by_velocity: Vec<usize> = (0..notes.len()).collect();
+        by_velocity.sort_by_key(|&index| Reverse(notes[index].velocity));
+        let kept: HashSet<usize> = by_velocity[..room].iter().copied().collect();
+        let (kept, dropped) = notes
+            .drain(..)
+            .enumerate()
+            .partition::<Vec<_>, _>(|(index, _)| kept.contains(index));
+        *notes = kept.into_iter().map(|(_, note)| note).collect();
+        dropped.into_iter().map(|(_, note)| note).collect()
+    }
+
+    // Frees the string of a note when it ends, or keeps it ringing under the sustain pedal
+    fn note_end(&mut self, note: (u8, u8), actions: &mut Vec<PlayerAction>) {
+        // A note held back by the rate limit that already ended is never played
+        self.pending_notes.retain(|(pending, _)| (pending.channel, pending.key) != note);
+        // Singing stops with the note even under the sustain pedal
+        if self.held_sing == Some(note) {
+            self.held_sing = None;
+            actions.push(PlayerAction::SingRelease);
+        }
+        if self.sustain[note.0 as usize] {
+            // Keep the string ringing until the pedal is released
+            self.sustained_notes.push(note);
+        } else {
+            // Notes ending on this tick free their strings for the ones starting on it
+            self.active_notes.remove(&note);
+        }
+    }
+
+    // Slides the strings the channel is sounding along with the bend
+    fn bend_channel(&mut self, channel: u8, bend: midly::PitchBend, actions: &mut Vec<PlayerAction>) {
+        let semitones = (bend.as_f64() * self.pitch_bend_range).round().clamp(-127.0, 127.0) as i8;
+        let offset = (semitones - self.pitch_bends[channel as usize]) as i32;
+        self.pitch_bends[channel as usize] = semitones;
+        if offset == 0 {
+            return;
+        }
+
+        let strings: Vec<i32> = self
+            .active_notes
+            .iter()
+            .filter(|(&(note_channel, _), _)| note_channel == channel)
+            .map(|(_, &string)| string)
+            .collect();
+        for string in strings {
+            let fret = self.cur_string_positions.get(&string).copied().unwrap_or(0) + offset;
+            if (0..=self.max_fret).contains(&fret) {
+                self.fret_action(string, fret, actions);
+            }
+        }
+    }
+
+    fn set_sustain(&mut self, channel: u8, held: bool) {
+        self.sustain[channel as usize] = held;
+        if held {
+            return;
+        }
+
+        // Release the notes that were only ringing because of the pedal
+        let (released, still_held) = self
+            .sustained_notes
+            .drain(..)
+            .partition(|&(note_channel, _)| note_channel == channel);
+        self.sustained_notes = still_held;
+        for note in released {
+            self.active_notes.remove(&note);
+        }
+    }
+
+    fn sort_by_priority(&self, notes: &mut [MidiNote]) {
+        match self.voice_priority {
+            VoicePriority::Highest => notes.sort_by_key(|note| Reverse(note.note)),
+            VoicePriority::Lowest => notes.sort_by_key(|note| note.note),
+            VoicePriority::Melody => {
+                // Rank each track by the highest note it plays on this tick
+                let mut track_tops: HashMap<u32, u8> = HashMap::new();
+                for note in notes.iter() {
+                    let top = track_tops.entry(note.track).or_default();
+                    *top = (*top).max(note.note);
+                }
+                notes.sort_by_key(|note| (Reverse(track_tops[&note.track]), Reverse(note.note)));
+            }
+        }
+    }
+
+    // Whether to sing along with the note, highest_note is the highest note starting on the same tick
+    fn sings(&self, note: u8, midi_note: MidiNote, highest_note: u8) -> bool {
+        if let Some(sing_tracks) = &self.sing_tracks {
+            if !sing_tracks.contains(&(midi_note.track as usize)) {
+                return false;
+            }
+        }
+
+        match self.sing_mode {
+            SingMode::AboveThreshold(sing_above) => note >= sing_above,
+            SingMode::MelodyTrack(track) => midi_note.track as usize == track,
+            SingMode::HighestNote => midi_note.note == highest_note,
+            SingMode::Off => false,
+        }
+    }
+
+    // Brings the note into the range of the guitar, None if it should be left out
+    fn fit_to_range(&self, note: u8) -> Option<u8> {
+        if (self.min_note..=self.max_note).contains(&note) {
+            return Some(note);
+        }
+
+        match self.range_strategy {
+            RangeStrategy::Clamp => Some(note.clamp(self.min_note, self.max_note)),
+            RangeStrategy::OctaveFold => {
+                let mut folded = note;
+                while folded < self.min_note {
+                    folded += 12;
+                }
+                while folded > self.max_note && folded >= 12 {
+                    folded -= 12;
+                }
+                // The range can be narrower than an octave with a limited fretboard
+                if !(self.min_note..=self.max_note).contains(&folded) {
+                    debug!("Note {} doesn't fold into range, clamping", note_name(note));
+                    return Some(note.clamp(self.min_note, self.max_note));
+                }
+                Some(folded)
+            }
+            RangeStrategy::Drop => {
+                debug!("Dropping note {} outside the guitar range", note_name(note));
+                None
+            }
+        }
+    }
+
+    fn note_actions(&mut self, midi_note: MidiNote, highest_note: u8) -> Vec<PlayerAction> {
+        let mut actions = Vec::new();
+        let Some(note) = self.fit_to_range(midi_note.note) else {
+            self.record_issue(midi_note, MappingReason::OutOfRange);
+            return actions;
+        };
+
+        if let Some(string) = self.fret_note(note, midi_note, &mut actions) {
+            // Strum the string
+            actions.push(PlayerAction::Strum { string });
+        }
+
+        if self.sings(note, midi_note, highest_note) {
+            self.sing_actions(midi_note, &mut actions);
+        }
+
+        actions
+    }
+
+    // Sets the frets for every note first and then strums them all at once
+    fn chord_actions(&mut self, notes: &[MidiNote], highest_note: u8) -> Vec<PlayerAction> {
+        let mut actions = Vec::new();
+        let mut strings = Vec::new();
+        let mut sung_note = None;
+
+        for &midi_note in notes {
+            let Some(note) = self.fit_to_range(midi_note.note) else {
+                self.record_issue(midi_note, MappingReason::OutOfRange);
+                continue;
+            };
+
+            if let Some(string) = self.fret_note(note, midi_note, &mut actions) {
+                strings.push(string);
+            }
+
+            if sung_note.is_none() && self.sings(note, midi_note, highest_note) {
+                sung_note = Some(midi_note);
+            }
+        }
+
+        if !strings.is_empty() {
+            actions.push(PlayerAction::StrumChord { strings });
+        }
+
+        if let Some(midi_note) = sung_note {
+            self.sing_actions(midi_note, &mut actions);
+        }
+
+        actions
+    }
+
+    // Taps the sing key, or holds it until the NoteOff of the note when holding is on
+    fn sing_actions(&mut self, midi_note: MidiNote, actions: &mut Vec<PlayerAction>) {
+        if !self.hold_sing {
+            actions.push(PlayerAction::Sing { note: midi_note.note });
+        } else {
+            // Let go of the previous note first so the new one is sung from its start
+            if self.held_sing.is_some() {
+                actions.push(PlayerAction::SingRelease);
+            }
+            self.held_sing = Some((midi_note.channel, midi_note.key));
+            actions.push(PlayerAction::SingHold { note: midi_note.note });
+        }
+
+        if self.vibrato_from_modwheel {
+            self.vibrato_actions(midi_note, actions);
+        }
+    }
+
+    // Wobbles the fret of the sung note a semitone and back, more often the further the modulation wheel is up
+    fn vibrato_actions(&self, midi_note: MidiNote, actions: &mut Vec<PlayerAction>) {
+        let depth = self.modulation[midi_note.channel as usize];
+        if depth == 0 {
+            return;
+        }
+        let Some(&string) = self.active_notes.get(&(midi_note.channel, midi_note.key)) else {
+            return;
+        };
+
+        let fret = self.cur_string_positions.get(&string).copied().unwrap_or(0);
+        let wobble = if fret < self.max_fret { fret + 1 } else { fret - 1 };
+        if wobble < 0 {
+            return;
+        }
+
+        let cycles = 1 + depth as usize * (MAX_VIBRATO_CYCLES - 1) / 127;
+        for _ in 0..cycles {
+            actions.push(PlayerAction::SetFret { string, fret: wobble });
+            actions.push(PlayerAction::SetFret { string, fret });
+        }
+    }
+
+    // Finds a string for the note and sets its fret, returns the string to strum
+    fn fret_note(
+        &mut self,
+        note: u8,
+        midi_note: MidiNote,
+        actions: &mut Vec<PlayerAction>,
+    ) -> Option<i32> {
+        // Finding the string marks it as used now, keep when it was last used before that
+        let previous_usage_time = self.last_string_usage_time.clone();
+
+        // Use the find_best_string function to get the guitar position
+        let planned_string = self
+            .fingering
+            .get(&(self.current_tick, midi_note.channel, midi_note.key))
+            .copied();
+        let Some(position) = self.find_best_string(note, planned_string) else {
+            debug!("No suitable string found for note {}", note_name(note));
+            self.record_issue(midi_note, MappingReason::StringsExhausted);
+            return None;
+        };
+
+        // The same fret strummed again right away sounds like a stutter, let the string keep ringing
+        let is_repeat = self.cur_string_positions.get(&position.string) == Some(&position.fret)
+            && previous_usage_time[position.string as usize].elapsed() < self.min_restrum;
+
+        info!(
+            "Playing note {} on string {} fret {} - track {}",
+            note_name(note),
+            position.string + 1,
+            position.fret,
+            midi_note.track
+        );
+
+        // Set fret position
+        self.fret_action(position.string, position.fret, actions);
+
+        self.strings_played[position.string as usize] = true;
+        // The new note cuts off whatever the string was sounding
+        self.active_notes
+            .retain(|_, &mut string| string != position.string);
+        self.active_notes
+            .insert((midi_note.channel, midi_note.key), position.string);
+
+        if is_repeat {
+            debug!("Not re-strumming string {} so soon", position.string + 1);
+            return None;
+        }
+        Some(position.string)
+    }
+
+    // Logs the action instead of sending input while keeping the same timing
+    fn log_dry_run_action(&self, action: &PlayerAction) {
+        let elapsed = self.song_elapsed_micros.load(atomic::Ordering::Relaxed);
+        info!("[Dry run] {:.3}s {:?}", elapsed as f64 / 1_000_000.0, action);
+
+        match action {
+            PlayerAction::Strum { .. } | PlayerAction::StrumChord { .. } | PlayerAction::Sing { .. } => {
+                sleep(self.press_hold());
+            }
+            PlayerAction::Wait(duration) => sleep(*duration),
+            PlayerAction::SetFret { .. } | PlayerAction::SingHold { .. } | PlayerAction::SingRelease => {}
+        }
+    }
+
+    // Resets the guitar to all open strings, with a capo each string is moved onto the capo fret
+    fn open_strings(&mut self, actions: &mut Vec<PlayerAction>) {
+        self.fret_action(self.string_count(), 0, actions);
+        if self.capo > 0 {
+            for string in 0..self.string_count() {
+                self.cur_string_positions.insert(string, 0);
+                actions.push(PlayerAction::SetFret { string, fret: 0 });
+            }
+        }
+    }
+
+    fn fret_action(&mut self, string: i32, fret: i32, actions: &mut Vec<PlayerAction>) {
+        // Don't attempt to change to this position if it's already set
+        if self.cur_string_positions.get(&string).unwrap_or(&-1) == &fret {
+            return;
+        }
+
+        let cur_string_pos = self.cur_string_positions.entry(string).or_default();
+        let previous = *cur_string_pos;
+        *cur_string_pos = fret;
+
+        // Step through the frets in between on a far jump, the open all button has nothing to slide
+        if let Some(max_jump) = self.max_fret_jump.filter(|_| string < self.string_count()) {
+            let step = if fret > previous { max_jump } else { -max_jump };
+            let mut between = previous;
+            while (fret - between).abs() > max_jump {
+                between += step;
+                actions.push(PlayerAction::SetFret { string, fret: between });
+            }
+        }
+
+        actions.push(PlayerAction::SetFret { string, fret });
+    }
+
+    fn perform_actions(&mut self, actions: &[PlayerAction]) -> Result<(), PlayerError> {
+        let mut fret_moved = false;
+        for action in actions {
+            // Give the game time to register the frets before they're strummed
+            match action {
+                PlayerAction::SetFret { .. } => fret_moved = true,
+                PlayerAction::Strum { .. } | PlayerAction::StrumChord { .. } if fret_moved => {
+                    self.flush_inputs()?;
+                    sleep(self.scaled_sleep(self.fret_to_strum_delay));
+                    fret_moved = false;
+                }
+                _ => {}
+            }
+
+            if self.dry_run {
+                self.log_dry_run_action(action);
+                continue;
+            }
+
+            let result = match action {
+                PlayerAction::SetFret { string, fret } => self.set_fret(*string, *fret),
+                PlayerAction::Strum { string } => self.strum(&[*string]),
+                PlayerAction::StrumChord { strings } => self.strum(strings),
+                PlayerAction::Sing { note } => self.sing(*note),
+                PlayerAction::SingHold { note } => {
+                    self.wait_for_gap();
+                    let key = self.contour_sing_key(*note);
+                    self.sing_key(key, true).map(|()| self.sing_key_down = Some(key))
+                }
+                PlayerAction::SingRelease => self.release_sing_key(),
+                PlayerAction::Wait(duration) => self.flush_inputs().map(|()| sleep(*duration)),
+            };
+
+            if let Err(e) = result {
+                if self.on_input_error == ContinueOrAbort::Abort {
+                    return Err(e);
+                }
+                warn!("Skipping a failed input for {:?}: {}", action, e);
+                // The fret may not have moved, set it again the next time the string is used
+                if let PlayerAction::SetFret { string, .. } = action {
+                    self.cur_string_positions.remove(string);
+                }
+            }
+        }
+
+        // X events are queued through the tick and sent together
+        self.flush_inputs()
+    }
+
+    // Sends the X events queued since the last flush in one go
+    #[cfg(all(feature = "silent_input", not(windows), not(target_os = "macos")))]
+    fn flush_inputs(&mut self) -> Result<(), PlayerError> {
+        // Nothing was queued before the display was opened
+        if let Some(display) = &mut self.display {
+            let display = display.get()?;
+            unsafe { XFlush(display) };
+        }
+        Ok(())
+    }
+
+    // Opens the display the first time input is sent when it wasn't opened with the player
+    #[cfg(all(feature = "silent_input", not(windows), not(target_os = "macos")))]
+    fn x_display(&mut self) -> Result<*mut Display, PlayerError> {
+        let display = match self.display.take() {
+            Some(display) => display,
+            None => XDisplay::open()?,
+        };
+        self.display.insert(display).get()
+    }
+
+    // The other backends send every input as it's made
+    #[cfg(not(all(feature = "silent_input", not(windows), not(target_os = "macos"))))]
+    fn flush_inputs(&mut self) -> Result<(), PlayerError> {
+        Ok(())
+    }
+
+    // Sends the event to the game window, XSendEvent returns 0 when it fails
+    #[cfg(all(feature = "silent_input", not(windows), not(target_os = "macos")))]
+    unsafe fn send_x_event(
+        &self,
+        display: *mut Display,
+        mask: c_long,
+        event: *mut XEvent,
+    ) -> Result<(), PlayerError> {
+        if XSendEvent(display, self.window.id().into(), True, mask, event) == 0 {
+            return Err(PlayerError::SendEvent);
+        }
+        Ok(())
+    }
+
+    // Looks up the keycode for the key in the current keyboard layout
+    #[cfg(all(feature = "silent_input", not(windows), not(target_os = "macos")))]
+    fn x_keycode(display: *mut Display, key: char) -> u32 {
+        // Latin-1 keysyms match the character, the rest of unicode is offset
+        let keysym = if (key as u32) < 0x100 {
+            key as u32
+        } else {
+            0x0100_0000 | key as u32
+        };
+        unsafe { XKeysymToKeycode(display, keysym.into()) as u32 }
+    }
+
+    // Taps the sing key, held long enough for the game to read it
+    fn sing(&mut self, note: u8) -> Result<(), PlayerError> {
+        self.wait_for_gap();
+        let key = self.contour_sing_key(note);
+        self.sing_key(key, true)?;
+        self.flush_inputs()?;
+
+        // NOTE: This sleep is needed for the game to read the input
+        // espesially when it is low FPS since it checks input
+        // once per frame
+        sleep(self.press_hold());
+
+        self.sing_key(key, false)?;
+        self.last_release = Some(Instant::now());
+
+        Ok(())
+    }
+
+    // The key to sing the note with, the up or down key when the melody rises or falls
+    // with contour singing, and the plain sing key otherwise or on repeated notes
+    fn contour_sing_key(&mut self, note: u8) -> char {
+        let previous = self.last_sung_note.replace(note);
+        if !self.contour_singing {
+            return self.key_map.sing;
+        }
+
+        match previous.map(|previous| note.cmp(&previous)) {
+            Some(Ordering::Greater) => self.key_map.sing_up,
+            Some(Ordering::Less) => self.key_map.sing_down,
+            _ => self.key_map.sing,
+        }
+    }
+
+    // Lets go of the sing key if it's held for a note
+    fn release_sing_key(&mut self) -> Result<(), PlayerError> {
+        let Some(key) = self.sing_key_down else {
+            return Ok(());
+        };
+
+        self.sing_key(key, false)?;
+        // Not only called for a tick, so the release is sent right away
+        self.flush_inputs()?;
+        self.sing_key_down = None;
+        self.last_release = Some(Instant::now());
+
+        Ok(())
+    }
+
+    #[cfg(all(feature = "silent_input", not(windows), not(target_os = "macos")))]
+    fn sing_key(&mut self, key: char, pressed: bool) -> Result<(), PlayerError> {
+        let display = self.x_display()?;
         unsafe {
-            // Get the display and window ID
-            let display = self.display;
+            // Get the window ID
             let window_id = self.window.id();
 
-            // Create KeyPress event
+            // Create KeyPress or KeyRelease event
             let mut event = XKeyEvent {
-                type_: KeyPress,
+                type_: if pressed { KeyPress } else { KeyRelease },
                 serial: 0,
                 send_event: 0,
                 display,
@@ -534,87 +4858,258 @@ impl<'a> WebfishingPlayer<'a> {
                 y: 0,
                 x_root: 0,
                 y_root: 0,
-                keycode: 42, // g
+                keycode: WebfishingPlayer::x_keycode(display, key),
                 state: 0,
                 same_screen: 1,
                 time: CurrentTime,
             };
 
-            // Send KeyPress event
-            XSendEvent(display, window_id.into(), True, KeyPressMask, &mut event as *mut _ as *mut XEvent);
-            XFlush(display);
+            // Send the event
+            let mask = if pressed { KeyPressMask } else { KeyReleaseMask };
+            self.send_x_event(display, mask, &mut event as *mut _ as *mut XEvent)?;
+        }
+
+        Ok(())
+    }
+
+    #[cfg(all(feature = "silent_input", target_os = "macos"))]
+    fn sing_key(&mut self, key: char, pressed: bool) -> Result<(), PlayerError> {
+        let keycode = mac_keycode(key).ok_or(PlayerError::SendEvent)?;
+        let event = CGEvent::new_keyboard_event(mac_event_source()?, keycode, pressed);
+        self.post_mac_event(event)
+    }
+
+    // Posts key down/up events straight to the game process without focusing it
+    #[cfg(all(feature = "silent_input", target_os = "macos"))]
+    fn post_mac_keys(&mut self, keycodes: &[CGKeyCode]) -> Result<(), PlayerError> {
+        self.wait_for_gap();
+        for &keycode in keycodes {
+            let event = CGEvent::new_keyboard_event(mac_event_source()?, keycode, true);
+            self.post_mac_event(event)?;
+        }
+
+        // NOTE: This sleep is needed for the game to read the input
+        // espesially when it is low FPS since it checks input
+        // once per frame
+        sleep(self.press_hold());
+
+        for &keycode in keycodes {
+            let event = CGEvent::new_keyboard_event(mac_event_source()?, keycode, false);
+            self.post_mac_event(event)?;
+        }
+        self.last_release = Some(Instant::now());
+
+        Ok(())
+    }
+
+    #[cfg(all(feature = "silent_input", target_os = "macos"))]
+    fn post_mac_event(&self, event: Result<CGEvent, ()>) -> Result<(), PlayerError> {
+        let event = event.map_err(|_| PlayerError::SendEvent)?;
+        event.post_to_pid(self.pid);
+        Ok(())
+    }
+
+    #[cfg(all(feature = "silent_input", windows))]
+    fn sing_key(&mut self, key: char, pressed: bool) -> Result<(), PlayerError> {
+        let key = virtual_key(key);
+        let hwnd = self.window.id() as usize as HWND;
+        unsafe {
+            if pressed {
+                post_message(hwnd, WM_KEYDOWN, key as WPARAM, key_lparam(key) as LPARAM)
+            } else {
+                let lparam = key_lparam(key) | 0xC000_0000;
+                post_message(hwnd, WM_KEYUP, key as WPARAM, lparam as LPARAM)
+            }
+        }
+    }
+
+    // Posts key down/up messages straight to the game window without focusing it
+    #[cfg(all(feature = "silent_input", windows))]
+    fn post_keys(&mut self, keys: &[u16]) -> Result<(), PlayerError> {
+        self.wait_for_gap();
+        let hwnd = self.window.id() as usize as HWND;
+        unsafe {
+            for &key in keys {
+                post_message(hwnd, WM_KEYDOWN, key as WPARAM, key_lparam(key) as LPARAM)?;
+            }
 
             // NOTE: This sleep is needed for the game to read the input
             // espesially when it is low FPS since it checks input
             // once per frame
-            sleep(Duration::from_millis(self.input_sleep_duration));
-
-            // Create KeyRelease event
-            event.type_ = KeyRelease;
+            sleep(self.press_hold());
 
-            // Send KeyRelease event
-            XSendEvent(display, window_id.into(), True, KeyReleaseMask, &mut event as *mut _ as *mut XEvent);
-            XFlush(display);
+            for &key in keys {
+                // Key up also sets the previous key state and transition state bits
+                let lparam = key_lparam(key) | 0xC000_0000;
+                post_message(hwnd, WM_KEYUP, key as WPARAM, lparam as LPARAM)?;
+            }
         }
+        self.last_release = Some(Instant::now());
+
+        Ok(())
     }
 
     #[cfg(not(feature = "silent_input"))]
-    fn sing(&mut self) {
-        let key = Key::Unicode('g');
+    fn sing_key(&mut self, key: char, pressed: bool) -> Result<(), PlayerError> {
+        self.input_key(key, if pressed { Press } else { Release })
+    }
 
-        self.enigo.key(key, Press).unwrap();
-        // NOTE: This sleep is needed for the game to read the input
-        // espesially when it is low FPS since it checks input
-        // once per frame
-        sleep(Duration::from_millis(self.input_sleep_duration));
-        self.enigo.key(key, Release).unwrap();
+    // Connects enigo the first time it's needed when it wasn't connected with the player
+    fn enigo(&mut self) -> Result<&mut Enigo, PlayerError> {
+        let enigo = match self.enigo.take() {
+            Some(enigo) => enigo,
+            None => Enigo::new(&Settings::default())?,
+        };
+        Ok(self.enigo.insert(enigo))
     }
 
-    fn set_fret(&mut self, string: i32, fret: i32) {
-        // Don't attempt to change to this position if it's already set
-        if self.cur_string_positions.get(&string).unwrap_or(&-1) == &fret {
-            return;
+    // Inputs go through the uinput device on Wayland and enigo everywhere else
+    #[cfg(not(feature = "silent_input"))]
+    fn input_key(&mut self, key: char, direction: Direction) -> Result<(), PlayerError> {
+        #[cfg(all(feature = "wayland", target_os = "linux", not(feature = "silent_input")))]
+        if let Some(device) = &mut self.virtual_input {
+            for &pressed in direction_presses(direction) {
+                device.key(key, pressed).map_err(PlayerError::VirtualInput)?;
+            }
+            return Ok(());
         }
 
-        let cur_string_pos = self.cur_string_positions.entry(string).or_default();
-        *cur_string_pos = fret;
+        self.enigo()?.key(Key::Unicode(key), direction)?;
+        Ok(())
+    }
+
+    #[cfg(not(feature = "silent_input"))]
+    fn input_button(&mut self, direction: Direction) -> Result<(), PlayerError> {
+        #[cfg(all(feature = "wayland", target_os = "linux", not(feature = "silent_input")))]
+        if let Some(device) = &mut self.virtual_input {
+            for &pressed in direction_presses(direction) {
+                device
+                    .button(self.fret_button.uinput_button(), pressed)
+                    .map_err(PlayerError::VirtualInput)?;
+            }
+            return Ok(());
+        }
 
-        // These values need to be adjusted based on your screen resolution and game window position
-        let scale_x = self.window.width() as f32 / 2560.0;
-        let scale_y = self.window.height() as f32 / 1440.0;
+        let button = self.fret_button.enigo_button();
+        self.enigo()?.button(button, direction)?;
+        Ok(())
+    }
 
-        // Offset from the left where the strings start
-        let scaled_left = (460.0 * scale_x) as i32;
-        // Offset from the top where the frets start
-        let scaled_top = (130.0 * scale_y) as i32;
-        // Distance centre to centre of the strings
-        let scaled_string = (44.0 * scale_x) as i32;
-        // Distance centre to centre of the frets
-        let scaled_fret = (82.0 * scale_y) as i32;
+    #[cfg(not(feature = "silent_input"))]
+    fn input_move_mouse(&mut self, x: i32, y: i32) -> Result<(), PlayerError> {
+        #[cfg(all(feature = "wayland", target_os = "linux", not(feature = "silent_input")))]
+        if let Some(device) = &mut self.virtual_input {
+            return device.move_to(x, y).map_err(PlayerError::VirtualInput);
+        }
 
-        let fret_x = self.window.x() + (scaled_left + (string * scaled_string));
-        let fret_y = self.window.y() + (scaled_top + (fret * scaled_fret));
+        self.enigo()?.move_mouse(x, y, Coordinate::Abs)?;
+        Ok(())
+    }
 
-        info!(
-            "x: {} y: {} | scale_x {:.3} scale_y {:.3}",
-            fret_x, fret_y, scale_x, scale_y
-        );
+    fn set_fret(&mut self, string: i32, fret: i32) -> Result<(), PlayerError> {
+        // The open all button past the highest string has no fret to move up
+        let fret = if string < self.string_count() { fret + self.capo } else { fret };
+        let (fret_x, fret_y) = self.calibration.fret_position(self.window, string, fret);
+        info!("x: {} y: {}", fret_x, fret_y);
 
-        self.send_fret_input(fret_x, fret_y);
+        self.send_fret_input(fret_x, fret_y)
     }
 
     #[cfg(not(feature = "silent_input"))]
-    fn send_fret_input(&mut self, fret_x: i32, fret_y: i32) {
-        self.enigo
-            .move_mouse(fret_x, fret_y, Coordinate::Abs)
-            .unwrap();
-        self.enigo.button(Button::Left, Click).unwrap();
+    fn send_fret_input(&mut self, fret_x: i32, fret_y: i32) -> Result<(), PlayerError> {
+        let ((press_x, press_y), hold, (release_x, release_y)) =
+            self.fret_input_mode.points((fret_x, fret_y), self.press_hold());
+
+        self.wait_for_gap();
+        self.input_move_mouse(press_x, press_y)?;
+        if self.fret_input_mode == FretInputMode::Click {
+            self.input_button(Click)?;
+        } else {
+            self.input_button(Press)?;
+            sleep(hold);
+            self.input_move_mouse(release_x, release_y)?;
+            self.input_button(Release)?;
+        }
+        self.last_release = Some(Instant::now());
+
+        Ok(())
+    }
+
+    #[cfg(all(feature = "silent_input", target_os = "macos"))]
+    fn send_fret_input(&mut self, fret_x: i32, fret_y: i32) -> Result<(), PlayerError> {
+        let (press, hold, release) = self.fret_input_mode.points((fret_x, fret_y), self.press_hold());
+        let window_id = self.window.id() as i64;
+        let (down, dragged, up, button) = self.fret_button.mac_events();
+
+        let mut events = vec![(down, press)];
+        if release != press {
+            events.push((dragged, release));
+        }
+        events.push((up, release));
+
+        self.wait_for_gap();
+        for (index, (event_type, (x, y))) in events.into_iter().enumerate() {
+            // Hold the button between the press and what follows it
+            if index == 1 {
+                sleep(hold);
+            }
+            let point = CGPoint::new(x as f64, y as f64);
+            let event = CGEvent::new_mouse_event(mac_event_source()?, event_type, point, button)
+                .map_err(|_| PlayerError::SendEvent)?;
+            // Target the game window so the click doesn't depend on the pointer being over it
+            event.set_integer_value_field(EventField::MOUSE_EVENT_WINDOW_UNDER_MOUSE_POINTER, window_id);
+            event.set_integer_value_field(
+                EventField::MOUSE_EVENT_WINDOW_UNDER_MOUSE_POINTER_THAT_CAN_HANDLE_THIS_EVENT,
+                window_id,
+            );
+            self.post_mac_event(Ok(event))?;
+        }
+        self.last_release = Some(Instant::now());
+
+        Ok(())
+    }
+
+    #[cfg(all(feature = "silent_input", windows))]
+    fn send_fret_input(&mut self, fret_x: i32, fret_y: i32) -> Result<(), PlayerError> {
+        let hwnd = self.window.id() as usize as HWND;
+        let (press, hold, release) = self.fret_input_mode.points((fret_x, fret_y), self.press_hold());
+
+        // Mouse messages use coordinates relative to the client area
+        let client_lparam = |(x, y): (i32, i32)| {
+            let mut point = POINT { x, y };
+            if unsafe { ScreenToClient(hwnd, &mut point) } == 0 {
+                return Err(PlayerError::SendEvent);
+            }
+            Ok((((point.y as u32 & 0xFFFF) << 16) | (point.x as u32 & 0xFFFF)) as LPARAM)
+        };
+        let press_lparam = client_lparam(press)?;
+        let release_lparam = client_lparam(release)?;
+        let (down, up, held) = self.fret_button.window_messages();
+
+        self.wait_for_gap();
+        unsafe {
+            post_message(hwnd, WM_MOUSEMOVE, 0, press_lparam)?;
+            post_message(hwnd, down, held, press_lparam)?;
+            sleep(hold);
+            if release != press {
+                post_message(hwnd, WM_MOUSEMOVE, held, release_lparam)?;
+            }
+            post_message(hwnd, up, 0, release_lparam)?;
+        }
+        self.last_release = Some(Instant::now());
+
+        Ok(())
     }
 
-    #[cfg(feature = "silent_input")]
-    fn send_fret_input(&mut self, fret_x: i32, fret_y: i32) {
+    #[cfg(all(feature = "silent_input", not(windows), not(target_os = "macos")))]
+    fn send_fret_input(&mut self, fret_x: i32, fret_y: i32) -> Result<(), PlayerError> {
+        let display = self.x_display()?;
+        let ((press_x, press_y), hold, (release_x, release_y)) =
+            self.fret_input_mode.points((fret_x, fret_y), self.press_hold());
+        let (button, button_state, motion_mask) = self.fret_button.x11_button();
+        self.wait_for_gap();
         unsafe {
-            let root = XDefaultRootWindow(self.display);
+            let root = XDefaultRootWindow(display);
             let window_id = self.window.id();
 
             // Create KeyPress event without moving the mouse pointer
@@ -622,49 +5117,95 @@ impl<'a> WebfishingPlayer<'a> {
                 type_: ButtonPress,
                 serial: 0,
                 send_event: 0,
-                display: self.display,
+                display,
                 window: window_id.into(),
                 root,
                 subwindow: 0,
-                x: fret_x,
-                y: fret_y,
-                x_root: fret_x,
-                y_root: fret_y,
-                button: 1, // Left button
+                x: press_x,
+                y: press_y,
+                x_root: press_x,
+                y_root: press_y,
+                button,
                 same_screen: 1,
                 state: 0,
                 time: CurrentTime,
             };
 
             // Send the button press event
-            XSendEvent(self.display, window_id.into(), True, ButtonPressMask.into(), &mut event as *mut _ as *mut XEvent);
-            XFlush(self.display);
+            self.send_x_event(display, ButtonPressMask, &mut event as *mut _ as *mut XEvent)?;
+            // A plain click goes out with the rest of the tick
+            if !hold.is_zero() {
+                XFlush(display);
+                sleep(hold);
+            }
+
+            // Drag to the release point with the button held
+            if (release_x, release_y) != (press_x, press_y) {
+                let mut motion = XMotionEvent {
+                    type_: MotionNotify,
+                    serial: 0,
+                    send_event: 0,
+                    display,
+                    window: window_id.into(),
+                    root,
+                    subwindow: 0,
+                    x: release_x,
+                    y: release_y,
+                    x_root: release_x,
+                    y_root: release_y,
+                    state: button_state,
+                    is_hint: 0,
+                    same_screen: 1,
+                    time: CurrentTime,
+                };
+                self.send_x_event(display, motion_mask, &mut motion as *mut _ as *mut XEvent)?;
+            }
 
             // Change the event type to button release
             event.type_ = ButtonRelease;
+            event.x = release_x;
+            event.y = release_y;
+            event.x_root = release_x;
+            event.y_root = release_y;
 
             // Send the button release event
-            XSendEvent(self.display, window_id.into(), True, ButtonReleaseMask.into(), &mut event as *mut _ as *mut XEvent);
-            XFlush(self.display);
+            self.send_x_event(display, ButtonReleaseMask, &mut event as *mut _ as *mut XEvent)?;
         }
+        self.last_release = Some(Instant::now());
+
+        Ok(())
     }
 
-    #[cfg(feature = "silent_input")]
-    fn strum_string(&mut self, string: i32) {
-        // Map the string index to the corresponding keycode
-        let keycode = match string {
-            0 => 24, // Keycode for 'q'
-            1 => 25, // Keycode for 'w'
-            2 => 26, // Keycode for 'e'
-            3 => 27, // Keycode for 'r'
-            4 => 28, // Keycode for 't'
-            5 => 29, // Keycode for 'y'
-            _ => return,
-        };
+    fn strum(&mut self, strings: &[i32]) -> Result<(), PlayerError> {
+        self.strum_strings(strings)?;
+        if !self.double_strum {
+            return Ok(());
+        }
+
+        // The first strum has to reach the game before the gap
+        self.flush_inputs()?;
+        sleep(self.scaled_sleep(DOUBLE_STRUM_GAP));
+        debug!("Strumming strings {:?} again", strings);
+        self.strum_strings(strings)
+    }
+
+    // Strums all the given strings within a single press/release window
+    #[cfg(all(feature = "silent_input", not(windows), not(target_os = "macos")))]
+    fn strum_strings(&mut self, strings: &[i32]) -> Result<(), PlayerError> {
+        let display = self.x_display()?;
+        // Map the string indexes to the corresponding keycodes
+        let keycodes: Vec<u32> = strings
+            .iter()
+            .filter_map(|&string| self.key_map.strings.get(string as usize))
+            .map(|&key| WebfishingPlayer::x_keycode(display, key))
+            .collect();
+        if keycodes.is_empty() {
+            return Ok(());
+        }
 
+        self.wait_for_gap();
         unsafe {
-            // Get the display and window ID
-            let display = self.display;
+            // Get the window ID
             let window_id = self.window.id();
 
             // Create KeyPress event
@@ -680,88 +5221,277 @@ impl<'a> WebfishingPlayer<'a> {
                 y: 0,
                 x_root: 0,
                 y_root: 0,
-                keycode: keycode,
+                keycode: 0,
                 state: 0,
                 same_screen: 1,
                 time: CurrentTime,
             };
 
-            // Send KeyPress event
-            XSendEvent(display, window_id.into(), True, KeyPressMask, &mut event as *mut _ as *mut XEvent);
+            // Send KeyPress events
+            for &keycode in &keycodes {
+                event.keycode = keycode;
+                self.send_x_event(display, KeyPressMask, &mut event as *mut _ as *mut XEvent)?;
+            }
             XFlush(display);
 
             // NOTE: This sleep is needed for the game to read the input
             // espesially when it is low FPS since it checks input
             // once per frame
-            sleep(Duration::from_millis(self.input_sleep_duration));
+            sleep(self.press_hold());
 
             // Create KeyRelease event
             event.type_ = KeyRelease;
 
-            // Send KeyRelease event
-            XSendEvent(display, window_id.into(), True, KeyReleaseMask, &mut event as *mut _ as *mut XEvent);
-            XFlush(display);
+            // Send KeyRelease events
+            for &keycode in &keycodes {
+                event.keycode = keycode;
+                self.send_x_event(display, KeyReleaseMask, &mut event as *mut _ as *mut XEvent)?;
+            }
+        }
+        self.last_release = Some(Instant::now());
+
+        Ok(())
+    }
+
+    // Strums all the given strings within a single press/release window
+    #[cfg(all(feature = "silent_input", target_os = "macos"))]
+    fn strum_strings(&mut self, strings: &[i32]) -> Result<(), PlayerError> {
+        let keycodes: Vec<CGKeyCode> = strings
+            .iter()
+            .filter_map(|&string| self.key_map.strings.get(string as usize))
+            .filter_map(|&key| mac_keycode(key))
+            .collect();
+        if keycodes.is_empty() {
+            return Ok(());
+        }
+
+        self.post_mac_keys(&keycodes)
+    }
+
+    // Strums all the given strings within a single press/release window
+    #[cfg(all(feature = "silent_input", windows))]
+    fn strum_strings(&mut self, strings: &[i32]) -> Result<(), PlayerError> {
+        let keys: Vec<u16> = strings
+            .iter()
+            .filter_map(|&string| self.key_map.strings.get(string as usize))
+            .map(|&key| virtual_key(key))
+            .collect();
+        if keys.is_empty() {
+            return Ok(());
         }
+
+        self.post_keys(&keys)
     }
 
+    // Strums all the given strings within a single press/release window
     #[cfg(not(feature = "silent_input"))]
-    fn strum_string(&mut self, string: i32) {
-        let key = match string {
-            0 => Key::Unicode('q'),
-            1 => Key::Unicode('w'),
-            2 => Key::Unicode('e'),
-            3 => Key::Unicode('r'),
-            4 => Key::Unicode('t'),
-            5 => Key::Unicode('y'),
-            _ => return,
-        };
-
-        self.enigo.key(key, Press).unwrap();
+    fn strum_strings(&mut self, strings: &[i32]) -> Result<(), PlayerError> {
+        let keys: Vec<char> = strings
+            .iter()
+            .filter_map(|&string| self.key_map.strings.get(string as usize))
+            .copied()
+            .collect();
+        if keys.is_empty() {
+            return Ok(());
+        }
+
+        self.wait_for_gap();
+        for &key in &keys {
+            self.input_key(key, Press)?;
+        }
         // NOTE: This sleep is needed for the game to read the input
         // espesially when it is low FPS since it checks input
         // once per frame
-        sleep(Duration::from_millis(self.input_sleep_duration));
-        self.enigo.key(key, Release).unwrap();
+        sleep(self.press_hold());
+        for &key in &keys {
+            self.input_key(key, Release)?;
+        }
+        self.last_release = Some(Instant::now());
+
+        Ok(())
+    }
+
+    // How long keys are held down, shorter at higher speeds when scaling with the speed
+    fn press_hold(&self) -> Duration {
+        self.scaled_sleep(self.press_hold)
+    }
+
+    // The least time between inputs, shorter at higher speeds when scaling with the speed
+    fn inter_note_gap(&self) -> Duration {
+        self.scaled_sleep(self.inter_note_gap)
+    }
+
+    fn scaled_sleep(&self, duration: Duration) -> Duration {
+        if !self.scale_sleep_with_speed {
+            return duration;
+        }
+        // Don't go below the floor, or lengthen sleeps that were already shorter than it
+        duration
+            .div_f64(self.playback_speed())
+            .max(MIN_SCALED_INPUT_SLEEP.min(duration))
+    }
+
+    // Waits until the gap since the last input was released has passed
+    fn wait_for_gap(&mut self) {
+        let Some(last_release) = self.last_release else {
+            return;
+        };
+        let gap = self.inter_note_gap().saturating_sub(last_release.elapsed());
+        if !gap.is_zero() {
+            // The inputs before the gap have to reach the game for it to keep them apart
+            if let Err(e) = self.flush_inputs() {
+                warn!("Failed to send the queued inputs: {}", e);
+            }
+            sleep(gap);
+        }
+    }
+
+    fn timecode_micros_per_tick(fps: Fps, subframe: u8) -> f64 {
+        1_000_000.0 / (fps.as_f32() as f64 * subframe as f64)
+    }
+
+    // Kept fractional so the ticks of very fast tempos or fine resolutions don't round to zero
+    fn tempo_micros_per_tick(tempo: u24, ticks_per_beat: u64) -> f64 {
+        tempo.as_int() as f64 / ticks_per_beat as f64
+    }
+
+    fn track_shift(&self, track: usize) -> i16 {
+        self.shift as i16 + self.track_shifts.get(&track).copied().unwrap_or(0) as i16
+    }
+
+    fn shifted_note(&self, key: u8, track: usize) -> u8 {
+        // Manual and per-track shifts can push the key past the MIDI range
+        (key as i16 + self.track_shift(track)).clamp(0, 127) as u8
+    }
+
+    pub fn shift(&self) -> i8 {
+        self.shift
+    }
+
+    pub fn song_info(&self) -> SongInfo {
+        SongInfo::from_source(self.source.as_ref())
+    }
+
+    pub fn playability_report(&self) -> PlayabilityReport {
+        let notes = WebfishingPlayer::get_unit_notes(self.source.as_ref(), self.skip_percussion);
+        let total_notes = notes.len();
+        let mut unplayable_octaves = [0; 11];
+        for (unit, note) in notes {
+            let shift = self.track_shift(unit);
+            if !WebfishingPlayer::is_playable(note, shift, self.min_note, self.max_note) {
+                unplayable_octaves[note as usize / 12] += 1;
+            }
+        }
+
+        let clamped_notes = unplayable_octaves.iter().sum();
+        PlayabilityReport {
+            total_notes,
+            playable_notes: total_notes - clamped_notes,
+            clamped_notes,
+            shift: self.shift,
+            unplayable_octaves,
+        }
+    }
+
+    fn is_playable(note: u8, shift: i16, min_note: u8, max_note: u8) -> bool {
+        (min_note as i16..=max_note as i16).contains(&(note as i16 + shift))
     }
 
-    fn get_notes(smf: &Smf) -> Vec<u8> {
-        smf.tracks
+    /// Tracks worth playing when none are chosen, the ones with a General MIDI guitar
+    /// (programs 24-31) and the melody track
+    pub fn auto_select_tracks(source: &dyn EventSource, skip_percussion: bool) -> Vec<usize> {
+        let mut tracks: Vec<usize> = SongInfo::from_source(source)
+            .tracks
             .iter()
-            .flat_map(|track| track)
-            .filter_map(|event| match event.kind {
-                TrackEventKind::Midi { ref message, .. } => Some(message),
-                _ => None,
-            })
-            .filter_map(|message| match message {
-                midly::MidiMessage::NoteOn { key, .. } => Some(key.as_int()),
-                _ => None,
-            })
-            .collect()
+            .enumerate()
+            .filter(|(_, track)| !track.is_drums && track.note_count > 0 && matches!(track.program, Some(24..=31)))
+            .map(|(unit, _)| unit)
+            .collect();
+        if let Some(melody) = WebfishingPlayer::melody_track(source, skip_percussion) {
+            if !tracks.contains(&melody) {
+                tracks.push(melody);
+                tracks.sort();
+            }
+        }
+        tracks
+    }
+
+    // The track with the highest average note, which usually carries the melody
+    fn melody_track(source: &dyn EventSource, skip_percussion: bool) -> Option<usize> {
+        let mut note_sums: BTreeMap<usize, (u64, u64)> = BTreeMap::new();
+        for (unit, note) in WebfishingPlayer::get_unit_notes(source, skip_percussion) {
+            let (sum, count) = note_sums.entry(unit).or_default();
+            *sum += note as u64;
+            *count += 1;
+        }
+        note_sums
+            .into_iter()
+            .map(|(unit, (sum, count))| (unit, sum as f64 / count as f64))
+            .max_by(|(_, a), (_, b)| a.total_cmp(b))
+            .map(|(unit, _)| unit)
+    }
+
+    // Every note with the track, or channel for single track songs, it's played on
+    fn get_unit_notes(source: &dyn EventSource, skip_percussion: bool) -> Vec<(usize, u8)> {
+        let format = source.header().format;
+        let mut notes = Vec::new();
+        for (track_num, track) in source.tracks().iter().enumerate() {
+            for event in track {
+                if let TrackEventKind::Midi {
+                    channel,
+                    message: midly::MidiMessage::NoteOn { key, .. },
+                } = event.kind
+                {
+                    if skip_percussion && channel == PERCUSSION_CHANNEL {
+                        continue;
+                    }
+
+                    let unit = selection_unit(format, track_num, &event.kind);
+                    notes.push((unit, key.as_int()));
+                }
+            }
+        }
+        notes
     }
 
-    fn calculate_optimal_shift(notes: &Vec<u8>) -> i8 {
+    /// The shift in semitones that brings the most of the notes into the range, the closest to 0 on a tie.
+    /// Notes are (track, note) pairs, the track is only used to favor one with `ShiftWeight::FavorTrack`
+    pub fn calculate_optimal_shift(notes: &[(usize, u8)], min_note: u8, max_note: u8, weight: ShiftWeight) -> i8 {
         let mut best_shift: i16 = 0;
+        let mut best_score = 0.0;
         let mut max_playable_notes = 0;
         let total_notes = notes.len();
 
+        let note_weight = |unit: usize, note: u8| match weight {
+            ShiftWeight::Uniform => 1.0,
+            ShiftWeight::FavorHigh => 1.0 + note as f64 / 12.0,
+            // Outweighs every other note together so the track is kept in range first
+            ShiftWeight::FavorTrack(track) if unit == track => total_notes as f64 + 1.0,
+            ShiftWeight::FavorTrack(_) => 1.0,
+        };
+
         for shift in -127..=127i16 {
-            let playable_notes = notes
-                .iter()
-                .filter(|&&n| {
-                    (n as i16 + shift) >= MIN_NOTE as i16 && (n as i16 + shift) <= MAX_NOTE as i16
-                })
-                .count();
+            let mut score = 0.0;
+            let mut playable_notes = 0;
+            for &(unit, note) in notes {
+                if WebfishingPlayer::is_playable(note, shift, min_note, max_note) {
+                    score += note_weight(unit, note);
+                    playable_notes += 1;
+                }
+            }
 
-            // The best shift is the one with the most playable notes that is closest to 0
-            if playable_notes > max_playable_notes
-                || (playable_notes == max_playable_notes && shift.abs() < best_shift.abs())
-            {
+            // The best shift is the one with the most weighted playable notes that is closest to 0
+            if score > best_score || (score == best_score && shift.abs() < best_shift.abs()) {
+                best_score = score;
                 max_playable_notes = playable_notes;
                 best_shift = shift;
             }
         }
 
         info!("Optimal shift: {}", best_shift);
+        if total_notes == 0 {
+            return 0;
+        }
         info!(
             "Total notes: {} | Playable notes: {} | Clamped notes {} : {}% playable",
             total_notes,
@@ -773,3 +5503,418 @@ impl<'a> WebfishingPlayer<'a> {
         best_shift as i8
     }
 }
+
+/// Several players sending to their own game windows off one clock,
+/// for duets where each window plays a different selection of tracks
+pub struct WebfishingEnsemble<'a> {
+    players: Vec<WebfishingPlayer<'a>>,
+}
+
+impl<'a> WebfishingEnsemble<'a> {
+    pub fn new(players: Vec<WebfishingPlayer<'a>>) -> Self {
+        WebfishingEnsemble { players }
+    }
+
+    /// Plays every player's part together, the hotkeys of the first player start and stop it
+    pub fn play(&mut self) -> Result<(), PlayerError> {
+        let Some(hotkeys) = self.players.first().map(|player| player.hotkeys) else {
+            return Ok(());
+        };
+        let device_state = DeviceState::new();
+
+        // Map every part up front so only sending the inputs is left to time
+        let mut timeline: Vec<(u64, usize, PlayerAction)> = Vec::new();
+        for (index, player) in self.players.iter_mut().enumerate() {
+            timeline.extend(
+                player
+                    .timed_actions()
+                    .into_iter()
+                    .map(|(time, action)| (time, index, action)),
+            );
+        }
+        // Stable so each player's actions on the same moment stay in order
+        timeline.sort_by_key(|&(time, _, _)| time);
+
+        println!("Press {} to start playing, {} to stop", hotkeys.start, hotkeys.quit);
+        while !device_state.get_keys().contains(&hotkeys.start) {
+            if self.players.iter().any(|player| player.should_stop()) {
+                info!("Song interrupted");
+                return Ok(());
+            }
+            sleep(POLL_INTERVAL);
+        }
+
+        // Every action is due at a fixed time from the start, so slow inputs don't add up
+        let start = Instant::now();
+        for (time, index, action) in timeline {
+            let due = Duration::from_micros(time);
+            loop {
+                if self.players.iter().any(|player| player.should_stop())
+                    || device_state.get_keys().contains(&hotkeys.quit)
+                {
+                    info!("Song interrupted");
+                    for player in &mut self.players {
+                        player.release_sing_key()?;
+                    }
+                    return Ok(());
+                }
+
+                let elapsed = start.elapsed();
+                if elapsed >= due {
+                    break;
+                }
+                sleep((due - elapsed).min(POLL_INTERVAL));
+            }
+
+            let player = &mut self.players[index];
+            player.song_elapsed_micros.store(time, atomic::Ordering::Relaxed);
+            player.perform_actions(&[action])?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use midly::{MetaMessage, MidiMessage, Timing};
+
+    // Nothing is sent on a dry run, the window only has to have a size
+    fn window() -> WindowInfo {
+        WindowInfo {
+            id: 0,
+            title: String::new(),
+            app_name: String::new(),
+            x: 0,
+            y: 0,
+            width: 2560,
+            height: 1440,
+            focused: true,
+            visible: true,
+        }
+    }
+
+    // Writes a MIDI file from (delta, event) lists, one per track
+    fn midi(format: Format, timing: Timing, tracks: &[&[(u32, TrackEventKind<'static>)]]) -> Vec<u8> {
+        let mut smf = Smf::new(Header::new(format, timing));
+        for track in tracks {
+            let mut events: Track = track
+                .iter()
+                .map(|&(delta, kind)| TrackEvent { delta: delta.into(), kind })
+                .collect();
+            events.push(TrackEvent {
+                delta: 0.into(),
+                kind: TrackEventKind::Meta(MetaMessage::EndOfTrack),
+            });
+            smf.tracks.push(events);
+        }
+        let mut data = Vec::new();
+        smf.write_std(&mut data).unwrap();
+        data
+    }
+
+    fn note_on(channel: u8, key: u8, vel: u8) -> TrackEventKind<'static> {
+        TrackEventKind::Midi {
+            channel: channel.into(),
+            message: MidiMessage::NoteOn { key: key.into(), vel: vel.into() },
+        }
+    }
+
+    fn note_off(channel: u8, key: u8) -> TrackEventKind<'static> {
+        TrackEventKind::Midi {
+            channel: channel.into(),
+            message: MidiMessage::NoteOff { key: key.into(), vel: 0.into() },
+        }
+    }
+
+    fn tempo(micros_per_beat: u32) -> TrackEventKind<'static> {
+        TrackEventKind::Meta(MetaMessage::Tempo(micros_per_beat.into()))
+    }
+
+    // A dry run player for the song that keeps the notes at their pitch
+    fn player(data: Vec<u8>, tracks: Vec<usize>, window: &WindowInfo) -> WebfishingPlayer<'_> {
+        let settings = PlayerSettings::builder()
+            .dry_run(true)
+            .manual_shift(0)
+            .tracks(tracks)
+            .build(data)
+            .unwrap();
+        WebfishingPlayer::new(settings, false, 0, window, None).unwrap()
+    }
+
+    #[test]
+    fn actions_of_a_short_phrase() {
+        let data = midi(
+            Format::Parallel,
+            Timing::Metrical(480.into()),
+            &[&[
+                (0, tempo(500_000)),
+                (0, note_on(0, 40, 100)),
+                (480, note_off(0, 40)),
+                (0, note_on(0, 47, 100)),
+                (480, note_off(0, 47)),
+            ]],
+        );
+        let window = window();
+        let mut player = player(data, vec![0], &window);
+        assert_eq!(
+            player.actions(),
+            [
+                PlayerAction::SetFret { string: 6, fret: 0 },
+                PlayerAction::Strum { string: 0 },
+                PlayerAction::Wait(Duration::from_millis(500)),
+                PlayerAction::SetFret { string: 1, fret: 2 },
+                PlayerAction::Strum { string: 1 },
+                PlayerAction::Wait(Duration::from_millis(500)),
+            ]
+        );
+    }
+
+    #[test]
+    fn timecode_tick_duration() {
+        // 25 fps with 40 ticks per frame is a millisecond per tick, tempo has no effect
+        let data = midi(
+            Format::Parallel,
+            Timing::Timecode(Fps::Fps25, 40),
+            &[&[
+                (0, tempo(250_000)),
+                (0, note_on(0, 40, 100)),
+                (500, note_off(0, 40)),
+            ]],
+        );
+        let window = window();
+        let mut player = player(data, vec![0], &window);
+        assert_eq!(player.micros_per_tick, 1000.0);
+        assert_eq!(
+            player.actions().last(),
+            Some(&PlayerAction::Wait(Duration::from_millis(500)))
+        );
+    }
+
+    #[test]
+    fn timecode_without_ticks_per_frame_is_rejected() {
+        let data = midi(
+            Format::Parallel,
+            Timing::Timecode(Fps::Fps25, 0),
+            &[&[(0, note_on(0, 40, 100)), (10, note_off(0, 40))]],
+        );
+        let settings = PlayerSettings::builder().dry_run(true).tracks(vec![0]).build(data).unwrap();
+        let window = window();
+        assert!(matches!(
+            WebfishingPlayer::new(settings, false, 0, &window, None),
+            Err(PlayerError::InvalidTiming(_))
+        ));
+    }
+
+    // The tick, selection unit and key of every note the player will start
+    fn note_starts(player: &WebfishingPlayer) -> Vec<(u64, u32, u8)> {
+        player
+            .events
+            .iter()
+            .filter_map(|event| match event.note_event() {
+                Some(NoteEvent::Start { key, .. }) => Some((event.absolute_time, event.track, key)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn single_track_is_selected_by_channel() {
+        let data = midi(
+            Format::SingleTrack,
+            Timing::Metrical(480.into()),
+            &[&[
+                (0, note_on(0, 40, 100)),
+                (0, note_on(2, 45, 100)),
+                (480, note_off(0, 40)),
+                (0, note_off(2, 45)),
+            ]],
+        );
+        let window = window();
+        let player = player(data, vec![2], &window);
+        assert_eq!(note_starts(&player), [(0, 2, 45)]);
+    }
+
+    #[test]
+    fn parallel_tracks_play_together() {
+        let data = midi(
+            Format::Parallel,
+            Timing::Metrical(480.into()),
+            &[
+                &[(0, note_on(0, 40, 100)), (480, note_off(0, 40))],
+                &[(0, note_on(1, 45, 100)), (480, note_off(1, 45))],
+            ],
+        );
+        let window = window();
+        let player = player(data, vec![0, 1], &window);
+        assert_eq!(note_starts(&player), [(0, 0, 40), (0, 1, 45)]);
+    }
+
+    #[test]
+    fn sequential_tracks_start_where_the_last_ended() {
+        let data = midi(
+            Format::Sequential,
+            Timing::Metrical(480.into()),
+            &[
+                &[(0, note_on(0, 40, 100)), (480, note_off(0, 40))],
+                &[(0, note_on(0, 45, 100)), (480, note_off(0, 45))],
+                &[(240, note_on(0, 47, 100)), (480, note_off(0, 47))],
+            ],
+        );
+        let window = window();
+        let player = player(data, vec![0, 1, 2], &window);
+        assert_eq!(note_starts(&player), [(0, 0, 40), (480, 1, 45), (1200, 2, 47)]);
+    }
+
+    #[test]
+    fn quantized_note_ends_after_its_start() {
+        // On at 5 and off at 7 both round to 8 on a grid of 8
+        let data = midi(
+            Format::Parallel,
+            Timing::Metrical(480.into()),
+            &[&[(5, note_on(0, 40, 100)), (2, note_off(0, 40))]],
+        );
+        let settings = PlayerSettings::builder()
+            .dry_run(true)
+            .tracks(vec![0])
+            .quantize(8)
+            .build(data)
+            .unwrap();
+        let window = window();
+        let player = WebfishingPlayer::new(settings, false, 0, &window, None).unwrap();
+        let notes: Vec<_> = player
+            .events
+            .iter()
+            .filter_map(|event| event.note_event().map(|note| (event.absolute_time, note)))
+            .collect();
+        assert_eq!(
+            notes,
+            [
+                (8, NoteEvent::Start { channel: 0, key: 40, vel: 100 }),
+                (16, NoteEvent::End { channel: 0, key: 40 }),
+            ]
+        );
+    }
+
+    #[test]
+    fn tempo_change_between_notes_of_other_tracks() {
+        // Half a second at 120 bpm, then a quarter second at 240 bpm
+        let data = midi(
+            Format::Parallel,
+            Timing::Metrical(480.into()),
+            &[
+                &[(0, tempo(500_000)), (480, tempo(250_000))],
+                &[(0, note_on(0, 40, 100)), (240, note_off(0, 40))],
+                &[(960, note_on(1, 45, 100)), (240, note_off(1, 45))],
+            ],
+        );
+        let window = window();
+        let mut player = player(data, vec![1, 2], &window);
+        let strums: Vec<_> = player
+            .timed_actions()
+            .into_iter()
+            .filter(|(_, action)| matches!(action, PlayerAction::Strum { .. }))
+            .collect();
+        assert_eq!(
+            strums,
+            [
+                (0, PlayerAction::Strum { string: 0 }),
+                (750_000, PlayerAction::Strum { string: 1 }),
+            ]
+        );
+    }
+
+    #[test]
+    fn note_off_and_zero_velocity_note_on_end_notes_alike() {
+        let phrase = |note_end: fn(u8, u8) -> TrackEventKind<'static>| {
+            midi(
+                Format::Parallel,
+                Timing::Metrical(480.into()),
+                &[&[
+                    (0, tempo(500_000)),
+                    (0, note_on(0, 40, 100)),
+                    (0, note_on(0, 45, 100)),
+                    (240, note_end(0, 40)),
+                    (0, note_on(0, 40, 100)),
+                    (240, note_end(0, 40)),
+                    (0, note_end(0, 45)),
+                    (0, note_on(0, 47, 100)),
+                    (480, note_end(0, 47)),
+                ]],
+            )
+        };
+        let window = window();
+        let mut note_off_player = player(phrase(note_off), vec![0], &window);
+        let mut note_on_player = player(phrase(|channel, key| note_on(channel, key, 0)), vec![0], &window);
+        let actions = note_off_player.actions();
+        assert!(actions.contains(&PlayerAction::Strum { string: 0 }));
+        assert_eq!(actions, note_on_player.actions());
+    }
+
+    #[test]
+    fn fast_tempo_at_high_resolution_keeps_its_length() {
+        // A 600µs beat split into 960 ticks makes each tick shorter than a microsecond
+        let data = midi(
+            Format::Parallel,
+            Timing::Metrical(960.into()),
+            &[&[
+                (0, tempo(600)),
+                (0, note_on(0, 40, 100)),
+                (960_000, note_off(0, 40)),
+                (0, note_on(0, 45, 100)),
+                (960, note_off(0, 45)),
+            ]],
+        );
+        let window = window();
+        let mut player = player(data, vec![0], &window);
+        // A thousand beats of 600µs
+        assert_eq!(player.millis_at_tick(960_000), 600);
+        assert_eq!(
+            player.timed_actions().last(),
+            Some(&(600_000, PlayerAction::Strum { string: 1 }))
+        );
+    }
+
+    #[test]
+    fn default_tempo_until_the_first_tempo_change() {
+        let data = midi(
+            Format::Parallel,
+            Timing::Metrical(480.into()),
+            &[&[
+                (0, note_on(0, 40, 100)),
+                (960, note_off(0, 40)),
+                (0, note_on(0, 45, 100)),
+                (480, note_off(0, 45)),
+            ]],
+        );
+        let window = window();
+        let mut player = player(data, vec![0], &window);
+        assert_eq!(player.millis_at_tick(960), 1000);
+        assert_eq!(
+            player.timed_actions().last(),
+            Some(&(1_000_000, PlayerAction::Strum { string: 1 }))
+        );
+    }
+
+    #[test]
+    fn bars_start_after_time_signature_changes() {
+        // A bar of 4/4, then bars of 3/4
+        let data = midi(
+            Format::Parallel,
+            Timing::Metrical(480.into()),
+            &[&[
+                (0, note_on(0, 40, 100)),
+                (1920, TrackEventKind::Meta(MetaMessage::TimeSignature(3, 2, 24, 8))),
+                (0, note_off(0, 40)),
+                (2880, note_on(0, 45, 100)),
+                (480, note_off(0, 45)),
+            ]],
+        );
+        let window = window();
+        let player = player(data, vec![0], &window);
+        assert_eq!(player.bar_start_tick(1), Some(0));
+        assert_eq!(player.bar_start_tick(2), Some(1920));
+        assert_eq!(player.bar_start_tick(4), Some(4800));
+    }
+}